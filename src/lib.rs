@@ -1,5 +1,10 @@
 #![crate_name = "micro_fs"]
 #![crate_type = "lib"]
 
+#[cfg(feature = "fuse")]
+extern crate fuser;
+#[cfg(feature = "fuse")]
+extern crate libc;
+
 pub mod micro_fs;
 pub use micro_fs::*;
\ No newline at end of file
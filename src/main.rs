@@ -1,6 +1,8 @@
 #![crate_name = "micro_fs"]
 
+use std::fs::File;
 use std::io;
+use std::path::Path;
 use std::process;
 
 #[macro_use]
@@ -15,37 +17,182 @@ fn main() {
     let matches = App::from_yaml(yaml).get_matches();
     
     let image = matches.value_of("image").unwrap();
-    let mut fs = MicroFS::new(image);
     
-    match matches.subcommand() {
+    let res = match matches.subcommand() {
         ("create", Some(create_matches)) => {
             let label = create_matches.value_of("label").unwrap();
             let bs = value_t!(create_matches.value_of("block_size"), u8).unwrap_or_else(|e| e.exit());
             let size = value_t!(create_matches.value_of("size"), usize).unwrap_or_else(|e| e.exit());
-            fs.create(label, bs, size);
+            let version = match create_matches.value_of("fat") {
+                Some("8") => Some(VERSION_FAT8),
+                Some("16") => Some(VERSION_FAT16),
+                Some("32") => Some(VERSION_FAT32),
+                _ => None
+            };
+            let root_blocks = value_t!(create_matches.value_of("root_blocks"), u32).unwrap_or_else(|e| e.exit());
+            let journal_blocks = create_matches.value_of("journal_blocks").map(|_| value_t!(create_matches.value_of("journal_blocks"), u32).unwrap_or_else(|e| e.exit()));
+            let mut flags = 0;
+            if create_matches.is_present("attributes") {
+                flags |= VERSION_ATTRIBUTES;
+            }
+            if create_matches.is_present("long_names") {
+                flags |= VERSION_LONG_NAMES;
+            }
+            if create_matches.is_present("checksums") {
+                flags |= VERSION_CHECKSUMS;
+            }
+            create(image, label, bs, size, version, root_blocks, journal_blocks, flags).map(|_| ())
+        },
+        ("mkfs", Some(mkfs_matches)) => {
+            let label = mkfs_matches.value_of("label").unwrap();
+            let bs = value_t!(mkfs_matches.value_of("block_size"), u8).unwrap_or_else(|e| e.exit());
+            let options = BuildOptions {
+                size: mkfs_matches.value_of("size").map(|_| value_t!(mkfs_matches.value_of("size"), usize).unwrap_or_else(|e| e.exit())),
+                padding: value_t!(mkfs_matches.value_of("padding"), usize).unwrap_or_else(|e| e.exit()),
+                sorted: mkfs_matches.is_present("sort"),
+                attributes: mkfs_matches.is_present("attributes"),
+                long_names: mkfs_matches.is_present("long_names"),
+                checksums: mkfs_matches.is_present("checksums")
+            };
+            mkfs(image, mkfs_matches.value_of("from").unwrap(), label, bs, &options)
         },
         ("add", Some(add_matches)) => {
-            fs.add(add_matches.value_of("file").unwrap());
-            fs.save();
+            let policy = match add_matches.value_of("alloc") {
+                Some("best-fit") => AllocPolicy::BestFit,
+                Some("contiguous") => AllocPolicy::Contiguous,
+                _ => AllocPolicy::FirstFit
+            };
+            MicroFS::open(image).and_then(|mut fs| {
+                add(&mut fs, add_matches.value_of("file").unwrap(), add_matches.value_of("dest").unwrap_or("/"), add_matches.is_present("force"), policy)?;
+                fs.save()
+            })
         },
         ("del", Some(del_matches)) => {
-            fs.del(del_matches.value_of("file").unwrap());
-            fs.save();
+            MicroFS::open(image).and_then(|mut fs| {
+                fs.del(del_matches.value_of("file").unwrap())?;
+                fs.save()
+            })
+        },
+        ("get", Some(get_matches)) => {
+            let filename = get_matches.value_of("file").unwrap();
+            let dest = get_matches.value_of("dest").unwrap_or_else(|| utils::split_parent(filename).1);
+            MicroFS::open(image).and_then(|mut fs| get(&mut fs, filename, dest))
+        },
+        ("mkdir", Some(mkdir_matches)) => {
+            MicroFS::open(image).and_then(|mut fs| {
+                fs.mkdir(mkdir_matches.value_of("dir").unwrap())?;
+                fs.save()
+            })
+        },
+        ("rmdir", Some(rmdir_matches)) => {
+            MicroFS::open(image).and_then(|mut fs| {
+                fs.rmdir(rmdir_matches.value_of("dir").unwrap(), rmdir_matches.is_present("recursive"))?;
+                fs.save()
+            })
+        },
+        ("mv", Some(mv_matches)) => {
+            MicroFS::open(image).and_then(|mut fs| {
+                fs.rename(mv_matches.value_of("old").unwrap(), mv_matches.value_of("new").unwrap())?;
+                fs.save()
+            })
+        },
+        ("export", Some(export_matches)) => {
+            MicroFS::open(image).and_then(|mut fs| export(&mut fs, export_matches.value_of("dest"), export_matches.is_present("tar")))
+        },
+        ("list", Some(list_matches)) => {
+            let format = Format::from(list_matches.value_of("format").unwrap());
+            MicroFS::open(image).and_then(|fs| list(&fs, list_matches.value_of("dir").unwrap_or("/"), format, list_matches.is_present("long")))
+        },
+        ("info", Some(info_matches)) => {
+            let format = Format::from(info_matches.value_of("format").unwrap());
+            MicroFS::open(image).map(|fs| info(&fs, format))
+        },
+        ("df", Some(df_matches)) => {
+            let format = Format::from(df_matches.value_of("format").unwrap());
+            MicroFS::open(image).and_then(|fs| match df_matches.values_of("check_fit") {
+                Some(files) => check_fit(&fs, &files.collect::<Vec<_>>(), df_matches.value_of("dest").unwrap())
+                    .map(|fits| if !fits { process::exit(1) }),
+                None => {
+                    df(&fs, format);
+                    Ok(())
+                }
+            })
+        },
+        ("resize", Some(resize_matches)) => {
+            let size = value_t!(resize_matches.value_of("size"), usize).unwrap_or_else(|e| e.exit());
+            MicroFS::open(image).and_then(|mut fs| {
+                fs.resize(size)?;
+                let info = fs.info();
+                println!("{} blocks of {} bytes, {} free", info.total_blocks, info.block_size, info.free_blocks);
+                Ok(())
+            })
+        },
+        ("defrag", Some(defrag_matches)) => {
+            MicroFS::open(image).and_then(|mut fs| defrag(&mut fs, defrag_matches.is_present("dry_run")))
+        },
+        ("touch", Some(touch_matches)) => {
+            let time = touch_matches.value_of("time").map(|_| value_t!(touch_matches.value_of("time"), u64).unwrap_or_else(|e| e.exit()));
+            MicroFS::open(image).and_then(|mut fs| {
+                fs.touch(touch_matches.value_of("path").unwrap(), time)?;
+                fs.save()
+            })
         },
-        ("list", Some(_matches)) => fs.list(),
-        ("info", Some(_matches)) => fs.info(),
+        ("chmod", Some(chmod_matches)) => {
+            let path = chmod_matches.value_of("path").unwrap();
+            let mode = chmod_matches.value_of("mode").unwrap();
+            if let Some(c) = mode.trim_start_matches(['+', '-', '=']).chars().find(|&c| !"rhsx".contains(c)) {
+                clap::Error::with_description(&format!("unknown attribute '{}' in '{}'", c, mode), clap::ErrorKind::InvalidValue).exit();
+            }
+            MicroFS::open(image).and_then(|mut fs| {
+                let attributes = parse_attributes(fs.find(path)?.attributes, mode);
+                fs.set_attributes(path, attributes)?;
+                fs.save()
+            })
+        },
+        ("verify", Some(_)) => {
+            MicroFS::open(image)
+                .and_then(|mut fs| verify(&mut fs))
+                .map(|intact| if !intact { process::exit(1) })
+        },
+        ("fsck", Some(fsck_matches)) => {
+            // problems left in the image are reported through the exit code
+            MicroFS::open_unchecked(image)
+                .and_then(|mut fs| fsck(&mut fs, fsck_matches.is_present("repair")))
+                .map(|consistent| if !consistent { process::exit(1) })
+        },
+        ("mount", Some(mount_matches)) => MicroFS::open(image).and_then(|fs| mount(fs, mount_matches.value_of("mountpoint").unwrap())),
         ("", None)        => {
+            let mut loaded = if Path::new(image).exists() {
+                match MicroFS::open(image) {
+                    Ok(fs) => {
+                        println!("\n{} is a valid image. You can modify it using the menu.", image);
+                        Some(fs)
+                    },
+                    Err(e) => {
+                        println!("\n{} is not a valid image ({}). You should create it first with the menu.", image, e);
+                        None
+                    }
+                }
+            } else {
+                println!("\n{} does not exist. You should create it first with the menu.", image);
+                None
+            };
             loop {
                 let mut choice = String::new();
                 
                 println!("\nMENU\n");
                 println!("0: quit");
         		println!("1: create <label> <block_size> <fs_size>");
-        		println!("2: add <file>");
+        		println!("2: add <file> [dest] [replace]");
         		println!("3: del <file>");
-        		println!("4: list");
+        		println!("4: list [dir]");
         		println!("5: info");
                 println!("6: save");
+                println!("7: get <file> [dest]");
+                println!("8: mkdir <dir>");
+                println!("9: rmdir <dir> [recursive]");
+                println!("10: fsck [repair]");
+                println!("11: mv <old> <new>");
                 
                 io::stdin().read_line(&mut choice).expect("Failed to read line !");
                 let choice : u8 = match choice.trim().parse() {
@@ -56,60 +203,532 @@ fn main() {
                     }
                 };
                 
-                match choice {
-                    0 => break,
-                    1 => {
-                        println!("\n[0] Label :");
-                        let mut label = String::new();
-                        io::stdin().read_line(&mut label).expect("Failed to read line !");
-                        label = label.trim().to_string();
-                        println!("[1] Block size :");
-                        let mut str_bs = String::new();
-                        io::stdin().read_line(&mut str_bs).expect("Failed to read line !");
-                        let bs = match str_bs.trim().parse() {
-                            Ok(num) => num,
-                            Err(_) => {
-                                println!("Not a number !");
-                                continue;
-                            }
-                        };
-                        println!("[2] FS size :");
-                        let mut str_size = String::new();
-                        io::stdin().read_line(&mut str_size).expect("Failed to read line !");
-                        let size = match str_size.trim().parse() {
-                            Ok(num) => num,
-                            Err(_) => {
-                                println!("Not a number !");
-                                continue;
-                            }
-                        };
-                        println!("");
-                        fs.create(&label, bs, size);
-                    },
+                if choice == 0 {
+                    break;
+                }
+                if choice == 1 {
+                    println!("\n[0] Label :");
+                    let mut label = String::new();
+                    io::stdin().read_line(&mut label).expect("Failed to read line !");
+                    label = label.trim().to_string();
+                    println!("[1] Block size :");
+                    let mut str_bs = String::new();
+                    io::stdin().read_line(&mut str_bs).expect("Failed to read line !");
+                    let bs = match str_bs.trim().parse() {
+                        Ok(num) => num,
+                        Err(_) => {
+                            println!("Not a number !");
+                            continue;
+                        }
+                    };
+                    println!("[2] FS size :");
+                    let mut str_size = String::new();
+                    io::stdin().read_line(&mut str_size).expect("Failed to read line !");
+                    let size = match str_size.trim().parse() {
+                        Ok(num) => num,
+                        Err(_) => {
+                            println!("Not a number !");
+                            continue;
+                        }
+                    };
+                    println!();
+                    match create(image, &label, bs, size, None, 1, None, 0) {
+                        Ok(fs) => loaded = Some(fs),
+                        Err(e) => println!("\nError: {}", e)
+                    }
+                    continue;
+                }
+                let fs = match loaded {
+                    Some(ref mut fs) => fs,
+                    None => {
+                        println!("No image loaded. You should create it first with the menu.");
+                        continue;
+                    }
+                };
+                let res = match choice {
                     2 => {
                         println!("\n[0] File :");
                         let mut filename = String::new();
                         io::stdin().read_line(&mut filename).expect("Failed to read line !");
                         filename = filename.trim().to_string();
-                        println!("");
-                        fs.add(&filename);
+                        println!("[1] Destination (empty for the root directory) :");
+                        let mut dest = String::new();
+                        io::stdin().read_line(&mut dest).expect("Failed to read line !");
+                        dest = dest.trim().to_string();
+                        if dest.is_empty() {
+                            dest = "/".to_string();
+                        }
+                        println!("[2] Replace an existing file (y/N) :");
+                        let mut force = String::new();
+                        io::stdin().read_line(&mut force).expect("Failed to read line !");
+                        println!();
+                        add(fs, &filename, &dest, force.trim() == "y", AllocPolicy::FirstFit)
                     },
                     3 => {
                         println!("\n[0] File :");
                         let mut filename = String::new();
                         io::stdin().read_line(&mut filename).expect("Failed to read line !");
                         filename = filename.trim().to_string();
-                        println!("");
-                        fs.del(&filename);
+                        println!();
+                        fs.del(&filename)
+                    },
+                    4 => {
+                        println!("\n[0] Directory (empty for the root directory) :");
+                        let mut dir = String::new();
+                        io::stdin().read_line(&mut dir).expect("Failed to read line !");
+                        dir = dir.trim().to_string();
+                        if dir.is_empty() {
+                            dir = "/".to_string();
+                        }
+                        list(fs, &dir, Format::Table, false)
+                    },
+                    5 => {
+                        info(fs, Format::Table);
+                        Ok(())
                     },
-                    4 => fs.list(),
-                    5 => fs.info(),
                     6 => fs.save(),
-                    _ => println!("Choice {} does not exist", choice),
+                    7 => {
+                        println!("\n[0] File :");
+                        let mut filename = String::new();
+                        io::stdin().read_line(&mut filename).expect("Failed to read line !");
+                        filename = filename.trim().to_string();
+                        println!("[1] Destination (empty for same name) :");
+                        let mut dest = String::new();
+                        io::stdin().read_line(&mut dest).expect("Failed to read line !");
+                        dest = dest.trim().to_string();
+                        if dest.is_empty() {
+                            dest = utils::split_parent(&filename).1.to_string();
+                        }
+                        println!();
+                        get(fs, &filename, &dest)
+                    },
+                    8 => {
+                        println!("\n[0] Directory :");
+                        let mut dir = String::new();
+                        io::stdin().read_line(&mut dir).expect("Failed to read line !");
+                        dir = dir.trim().to_string();
+                        println!();
+                        fs.mkdir(&dir)
+                    },
+                    9 => {
+                        println!("\n[0] Directory :");
+                        let mut dir = String::new();
+                        io::stdin().read_line(&mut dir).expect("Failed to read line !");
+                        dir = dir.trim().to_string();
+                        println!("[1] Remove its contents (y/N) :");
+                        let mut recursive = String::new();
+                        io::stdin().read_line(&mut recursive).expect("Failed to read line !");
+                        println!();
+                        fs.rmdir(&dir, recursive.trim() == "y")
+                    },
+                    10 => {
+                        println!("\n[0] Repair the problems found (y/N) :");
+                        let mut repair = String::new();
+                        io::stdin().read_line(&mut repair).expect("Failed to read line !");
+                        fsck(fs, repair.trim() == "y").map(|_| ())
+                    },
+                    11 => {
+                        println!("\n[0] File or directory :");
+                        let mut old = String::new();
+                        io::stdin().read_line(&mut old).expect("Failed to read line !");
+                        println!("[1] New path or destination directory :");
+                        let mut new = String::new();
+                        io::stdin().read_line(&mut new).expect("Failed to read line !");
+                        println!();
+                        fs.rename(old.trim(), new.trim())
+                    },
+                    _ => {
+                        println!("Choice {} does not exist", choice);
+                        Ok(())
+                    },
+                };
+                if let Err(e) = res {
+                    println!("\nError: {}", e);
                 }
             }
+            Ok(())
         },
         _           => unreachable!(),
+    };
+    if let Err(e) = res {
+        eprintln!("\nError: {}", e);
+        process::exit(1);
     }
     process::exit(0);
 }
+
+#[allow(clippy::too_many_arguments)]
+fn create(image: &str, label: &str, bs: u8, size: usize, version: Option<u16>, root_blocks: u32, journal_blocks: Option<u32>,
+          flags: u16) -> Result<MicroFS> {
+    if bs == 0 {
+        return Err(MicroFsError::BadSuperBlock("block size is zero"));
+    }
+    let mut sb = match version {
+        Some(version) => SuperBlock::with_version(label, bs, size, version),
+        None => SuperBlock::new(label, bs, size)
+    };
+    sb.set_root_blocks(root_blocks);
+    sb.version |= flags;
+    if let Some(journal_blocks) = journal_blocks {
+        sb.journal_blocks = journal_blocks;
+    }
+    let fs = MicroFS::format_with(image, sb)?;
+    println!("Super block written to image.");
+    println!("FAT written to image.");
+    println!("Rest of image written.");
+    println!("Total bytes = {}", (size / SECTOR_SIZE) * SECTOR_SIZE);
+    println!("Total sectors = {}", size / SECTOR_SIZE);
+    println!("Total blocks = {}", size / (SECTOR_SIZE * bs as usize));
+    Ok(fs)
+}
+
+fn export(fs: &mut MicroFS, dest: Option<&str>, tar: bool) -> Result<()> {
+    match (dest, tar) {
+        // nothing else goes to the standard output with the archive
+        (None, _) => fs.export_tar(&mut io::stdout().lock()).map(|_| ()),
+        (Some(dest), true) => {
+            let files = fs.export_tar(&mut File::create(dest)?)?;
+            println!("{} file(s) archived to {}", files, dest);
+            Ok(())
+        },
+        (Some(dest), false) => {
+            let files = fs.export_dir(dest)?;
+            println!("{} file(s) exported to {}", files, dest);
+            Ok(())
+        }
+    }
+}
+
+fn mkfs(image: &str, dir: &str, label: &str, bs: u8, options: &BuildOptions) -> Result<()> {
+    let fs = MicroFS::from_dir(image, dir, label, bs, options)?;
+    println!("{} copied to image.", dir);
+    println!("Total bytes = {}", fs.sb.blocks() * SECTOR_SIZE * bs as usize);
+    println!("Total blocks = {}", fs.sb.blocks());
+    Ok(())
+}
+
+#[cfg(feature = "fuse")]
+fn mount(fs: MicroFS, mountpoint: &str) -> Result<()> {
+    println!("Mounted on {}, unmount it to quit.", mountpoint);
+    Ok(micro_fs::mount::mount(fs, mountpoint)?)
+}
+
+#[cfg(not(feature = "fuse"))]
+fn mount(_fs: MicroFS, _mountpoint: &str) -> Result<()> {
+    Err(MicroFsError::Io(io::Error::other("built without FUSE support, rebuild with --features fuse")))
+}
+
+fn add(fs: &mut MicroFS, path: &str, dest: &str, force: bool, policy: AllocPolicy) -> Result<()> {
+    let target = if force {
+        fs.replace_with(path, dest, policy)?
+    } else {
+        fs.add_with(path, dest, policy)?
+    };
+    let size = fs.find(&target)?.size as usize;
+    println!("Entry size (bytes) = {}", size);
+    println!("Entry size (sectors) = {}", size / SECTOR_SIZE + 1);
+    println!("Entry size (blocks) = {}", size / (SECTOR_SIZE * fs.sb.block_size as usize) + 1);
+    Ok(())
+}
+
+fn get(fs: &mut MicroFS, filename: &str, dest: &str) -> Result<()> {
+    let size = fs.extract_to(filename, dest)?;
+    println!("Entry size (bytes) = {}", size);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Table,
+    Json,
+    Csv
+}
+impl From<&str> for Format {
+    fn from(format: &str) -> Format {
+        match format {
+            "json" => Format::Json,
+            "csv" => Format::Csv,
+            _ => Format::Table
+        }
+    }
+}
+
+fn json_str(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c)
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') || s.contains('\n') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// the ATTR_* flags as "rhsx", a '-' standing for each flag not set
+fn attribute_str(attributes: u8) -> String {
+    [(ATTR_READ_ONLY, 'r'), (ATTR_HIDDEN, 'h'), (ATTR_SYSTEM, 's'), (ATTR_EXECUTABLE, 'x')].iter()
+        .map(|&(flag, c)| if attributes & flag != 0 { c } else { '-' })
+        .collect()
+}
+
+// `mode` adds (+), removes (-) or sets (=, or no sign) the flags it lists to `attributes`
+fn parse_attributes(attributes: u8, mode: &str) -> u8 {
+    let (op, flags) = match mode.chars().next() {
+        Some(op) if op == '+' || op == '-' || op == '=' => (op, &mode[1..]),
+        _ => ('=', mode)
+    };
+    let mut mask = 0;
+    for c in flags.chars() {
+        mask |= match c {
+            'r' => ATTR_READ_ONLY,
+            'h' => ATTR_HIDDEN,
+            's' => ATTR_SYSTEM,
+            'x' => ATTR_EXECUTABLE,
+            _ => 0
+        };
+    }
+    match op {
+        '+' => attributes | mask,
+        '-' => attributes & !mask,
+        _ => mask
+    }
+}
+
+// seconds since the Unix epoch as "YYYY-MM-DD HH:MM:SS" in UTC
+fn date_str(time: u64) -> String {
+    let (days, seconds) = (time / 86400, time % 86400);
+    // the civil date of a day count, shifting the year to start in March so
+    // that the leap day comes last
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn list(fs: &MicroFS, dir: &str, format: Format, long: bool) -> Result<()> {
+    let entries = fs.list(dir)?;
+    // the times are only kept by images with attributes
+    let long = long && fs.sb.has_attributes();
+    match format {
+        Format::Json => {
+            let objects: Vec<String> = entries.iter().map(|e| {
+                let extra = if long {
+                    format!(", \"created\": {}, \"modified\": {}, \"attributes\": {}", e.created, e.modified, json_str(&attribute_str(e.attributes)))
+                } else {
+                    String::new()
+                };
+                format!(
+                    "  {{\"name\": {}, \"path\": {}, \"type\": \"{}\", \"size\": {}, \"children\": {}, \"start\": {}, \"blocks\": {}{}}}",
+                    json_str(&e.name), json_str(&e.path), if e.is_dir { "dir" } else { "file" }, e.size, e.children, e.start, e.blocks, extra
+                )
+            }).collect();
+            if objects.is_empty() {
+                println!("[]");
+            } else {
+                println!("[\n{}\n]", objects.join(",\n"));
+            }
+        },
+        Format::Csv => {
+            println!("name,path,type,size,children,start,blocks{}", if long { ",created,modified,attributes" } else { "" });
+            for e in &entries {
+                let extra = if long { format!(",{},{},{}", e.created, e.modified, attribute_str(e.attributes)) } else { String::new() };
+                println!("{},{},{},{},{},{},{}{}", csv_field(&e.name), csv_field(&e.path), if e.is_dir { "dir" } else { "file" },
+                         e.size, e.children, e.start, e.blocks, extra);
+            }
+        },
+        Format::Table => {
+            let width = entries.iter().map(|e| e.name.len() + 1).max().unwrap_or(0).max(4);
+            let header = format!("{:width$}  {:4}  {:>10}  {:>8}  {:>10}", "NAME", "TYPE", "SIZE", "ENTRIES", "BLOCKS", width = width);
+            if long {
+                println!("\n{}  {:4}  {:19}  MODIFIED", header, "ATTR", "CREATED");
+            } else {
+                println!("\n{}", header);
+            }
+            for e in &entries {
+                let name = if e.is_dir { format!("{}/", e.name) } else { e.name.clone() };
+                let children = if e.is_dir { e.children.to_string() } else { "-".to_string() };
+                let line = format!("{:width$}  {:4}  {:>10}  {:>8}  {:>10}", name, if e.is_dir { "dir" } else { "file" }, e.size, children, e.blocks, width = width);
+                if long {
+                    println!("{}  {:4}  {}  {}", line, attribute_str(e.attributes), date_str(e.created), date_str(e.modified));
+                } else {
+                    println!("{}", line);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn df(fs: &MicroFS, format: Format) {
+    let block_size = SECTOR_SIZE * fs.sb.block_size as usize;
+    let data_blocks = fs.data_blocks();
+    let used_blocks = fs.used_blocks();
+    let fields = [
+        ("block_size", block_size),
+        ("data_blocks", data_blocks),
+        ("used_blocks", used_blocks),
+        ("free_blocks", fs.free_blocks()),
+        ("free_bytes", fs.free_bytes()),
+        ("slack_bytes", fs.slack_bytes())
+    ];
+    match format {
+        Format::Json => {
+            let members: Vec<String> = fields.iter().map(|&(key, value)| format!("  \"{}\": {}", key, value)).collect();
+            println!("{{\n{}\n}}", members.join(",\n"));
+        },
+        Format::Csv => {
+            let keys: Vec<&str> = fields.iter().map(|&(key, _)| key).collect();
+            let values: Vec<String> = fields.iter().map(|&(_, value)| value.to_string()).collect();
+            println!("{}", keys.join(","));
+            println!("{}", values.join(","));
+        },
+        Format::Table => {
+            let percent = if data_blocks == 0 { 100 } else { (used_blocks * 100).div_ceil(data_blocks) };
+            println!("\n{:>10}  {:>10}  {:>10}  {:>10}  {:>4}", "BLOCKS", "USED", "FREE", "FREE BYTES", "USE%");
+            println!("{:>10}  {:>10}  {:>10}  {:>10}  {:>3}%", data_blocks, used_blocks, fs.free_blocks(), fs.free_bytes(), percent);
+            println!("Blocks of {} bytes, {} byte(s) lost past the end of files", block_size, fs.slack_bytes());
+        }
+    }
+}
+
+fn check_fit(fs: &MicroFS, files: &[&str], dest: &str) -> Result<bool> {
+    let report = fs.check_fit(files, dest)?;
+    println!("\n{} file(s) need {} block(s), {} free", report.files, report.needed_blocks, report.free_blocks);
+    if let Some(free) = report.free_entries {
+        println!("{} has room for {} more entries", dest, free);
+    }
+    println!("{}", if report.fits() { "They fit" } else { "They do not fit" });
+    Ok(report.fits())
+}
+
+fn print_fragmentation(stats: &FragStats) {
+    for &(ref path, fragments) in stats.fragments.iter().filter(|&&(_, fragments)| fragments > 1) {
+        println!("{} : {} fragments", path, fragments);
+    }
+    println!("{} of {} entries fragmented, {} free block(s), the largest run has {}",
+             stats.fragmented(), stats.fragments.len(), stats.free_blocks, stats.largest_free_run);
+}
+
+fn defrag(fs: &mut MicroFS, dry_run: bool) -> Result<()> {
+    if dry_run {
+        println!("\nFragmentation :");
+        print_fragmentation(&fs.fragmentation()?);
+        return Ok(());
+    }
+    let report = fs.defrag()?;
+    println!("\nBefore :");
+    print_fragmentation(&report.before);
+    println!("\nAfter :");
+    print_fragmentation(&report.after);
+    println!("\n{} block(s) moved", report.moved_blocks);
+    Ok(())
+}
+
+fn verify(fs: &mut MicroFS) -> Result<bool> {
+    let report = fs.verify()?;
+    for mismatch in &report.mismatches {
+        println!("{} : checksum {:08x}, expected {:08x}", mismatch.path, mismatch.actual, mismatch.expected);
+    }
+    if report.is_clean() {
+        println!("\n{} file(s) intact", report.files);
+    } else {
+        println!("\n{} of {} file(s) corrupted", report.mismatches.len(), report.files);
+    }
+    Ok(report.is_clean())
+}
+
+fn fsck(fs: &mut MicroFS, repair: bool) -> Result<bool> {
+    let report = if repair { fs.repair()? } else { fs.check()? };
+    println!("\n{} file(s), {} directories, {} block(s) in use", report.files, report.directories, report.used_blocks);
+    for problem in &report.problems {
+        println!("{}", problem);
+    }
+    if report.is_clean() {
+        println!("No problem found");
+    } else if repair {
+        println!("{} problem(s) repaired", report.problems.len());
+    } else {
+        println!("{} problem(s) found", report.problems.len());
+    }
+    Ok(report.is_clean() || repair)
+}
+
+fn info(fs: &MicroFS, format: Format) {
+    let info = fs.info();
+    let fields = [
+        ("label", json_str(&info.label)),
+        ("version", info.version.to_string()),
+        ("fat_bits", info.fat_bits.to_string()),
+        ("sector_size", info.sector_size.to_string()),
+        ("block_size", info.block_size.to_string()),
+        ("fat_size", info.fat_size.to_string()),
+        ("root_entry", info.root_entry.to_string()),
+        ("root_blocks", info.root_blocks.to_string()),
+        ("root_entries", info.root_entries.to_string()),
+        ("journal_blocks", info.journal_blocks.to_string()),
+        ("journal_start", info.journal_start.to_string()),
+        ("total_blocks", info.total_blocks.to_string()),
+        ("data_blocks", info.data_blocks.to_string()),
+        ("used_blocks", info.used_blocks.to_string()),
+        ("free_blocks", info.free_blocks.to_string())
+    ];
+    match format {
+        Format::Json => {
+            let members: Vec<String> = fields.iter().map(|&(key, ref value)| format!("  \"{}\": {}", key, value)).collect();
+            println!("{{\n{}\n}}", members.join(",\n"));
+        },
+        Format::Csv => {
+            let keys: Vec<&str> = fields.iter().map(|&(key, _)| key).collect();
+            let values: Vec<String> = fields[1..].iter().map(|(_, value)| value.clone()).collect();
+            println!("{}", keys.join(","));
+            println!("{},{}", csv_field(&info.label), values.join(","));
+        },
+        Format::Table => {
+            println!("\nFS info :");
+            println!("Label : {}", info.label);
+            let mut features = Vec::new();
+            if info.version & VERSION_ATTRIBUTES != 0 {
+                features.push("times and attributes");
+            }
+            if info.version & VERSION_LONG_NAMES != 0 {
+                features.push("long names");
+            }
+            if info.version & VERSION_CHECKSUMS != 0 {
+                features.push("checksums");
+            }
+            if features.is_empty() {
+                println!("Version : {}", info.version);
+            } else {
+                println!("Version : {} (entries with {})", info.version & 0xff, features.join(", "));
+            }
+            println!("Sector size : {} bytes", info.sector_size);
+            println!("Block size : {} bytes", info.block_size);
+            println!("FAT size : {} bytes ({}-bit entries)", info.fat_size, info.fat_bits);
+            println!("Root entry : {}", info.root_entry);
+            println!("Root directory : {} block(s), {} entries", info.root_blocks, info.root_entries);
+            if info.journal_blocks == 0 {
+                println!("Journal : none");
+            } else {
+                println!("Journal : {} block(s) from block {}", info.journal_blocks, info.journal_start);
+            }
+            println!("Blocks : {} in total, {} for data, {} used, {} free", info.total_blocks, info.data_blocks, info.used_blocks, info.free_blocks);
+        }
+    }
+}
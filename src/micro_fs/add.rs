@@ -1,31 +1,160 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
 use super::*;
 
-impl MicroFS {
-    pub fn add(&mut self, path: &str) {
+impl<D: BlockDevice> MicroFS<D> {
+    pub fn add(&mut self, path: &str) -> Result<()> {
+        self.add_as(path, "/")
+    }
+    
+    // `dest` is either a directory of the image, which receives the file under
+    // its host name, or the full path the file will have in the image
+    pub fn add_as(&mut self, path: &str, dest: &str) -> Result<()> {
+        self.add_with(path, dest, AllocPolicy::FirstFit).map(|_| ())
+    }
+    
+    // like `add_as`, with the blocks of the file picked following `policy`,
+    // the path the file got in the image is returned
+    pub fn add_with(&mut self, path: &str, dest: &str, policy: AllocPolicy) -> Result<String> {
+        let (target, file_buffer) = self.read_host(path, dest)?;
+        if self.find(&target).is_ok() {
+            return Err(MicroFsError::DuplicateName(target));
+        }
+        self.add_data(&target, file_buffer, policy)?;
+        self.set_host_attributes(path, &target, true)?;
+        Ok(target)
+    }
+    
+    // like `add_as`, but an existing file is overwritten: it gets a new chain in
+    // blocks the saved image does not use, its old chain being freed, so that an
+    // interrupted save leaves the old data whole
+    pub fn replace(&mut self, path: &str, dest: &str) -> Result<()> {
+        self.replace_with(path, dest, AllocPolicy::FirstFit).map(|_| ())
+    }
+    
+    // like `replace`, with the new chain picked following `policy`, the path
+    // the file got in the image is returned
+    pub fn replace_with(&mut self, path: &str, dest: &str, policy: AllocPolicy) -> Result<String> {
+        let (target, file_buffer) = self.read_host(path, dest)?;
+        let entry = match self.find(&target) {
+            Ok(e) if e.is_dir() => return Err(MicroFsError::IsADirectory(target)),
+            Ok(e) if e.is_read_only() => return Err(MicroFsError::ReadOnly(target)),
+            Ok(e) => Entry { start: e.start, size: e.size, ..Entry::new("") },
+            Err(MicroFsError::NotFound(_)) => {
+                self.add_data(&target, file_buffer, policy)?;
+                self.set_host_attributes(path, &target, true)?;
+                return Ok(target);
+            },
+            Err(e) => return Err(e)
+        };
+        // the old chain is given back first, the allocator only takes its blocks
+        // when nothing else will do
+        let old = self.get_blocks(&entry)?;
+        self.update_fat(&old, false);
+        let count = file_buffer.len() / (SECTOR_SIZE * self.sb.block_size as usize) + 1;
+        let blocks = match self.allocate(count, policy) {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                self.update_fat(&old, true);
+                return Err(e);
+            }
+        };
+        self.update_fat(&blocks, true);
+        let replaced = self.find_mut(&target)?;
+        replaced.start = blocks[0] as u32;
+        replaced.size = file_buffer.len() as u32;
+        self.pending.retain(|p| p.start != entry.start);
+        self.pending.push(PendingWrite { start: blocks[0] as u32, data: file_buffer });
+        self.set_host_attributes(path, &target, false)?;
+        Ok(target)
+    }
+    
+    // take the times and attributes of `target` from the host file `path`, a
+    // replaced file keeps its creation time and the attributes set in the image
+    fn set_host_attributes(&mut self, path: &str, target: &str, new: bool) -> Result<()> {
+        if !self.sb.has_attributes() {
+            return Ok(());
+        }
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified().map(unix_time).unwrap_or_else(|_| now());
+        let entry = self.find_mut(target)?;
+        entry.modified = modified;
+        if !new {
+            return Ok(());
+        }
+        entry.created = metadata.created().map(unix_time).unwrap_or(modified);
+        entry.attributes = 0;
+        if metadata.permissions().readonly() {
+            entry.attributes |= ATTR_READ_ONLY;
+        }
+        if is_executable(&metadata) {
+            entry.attributes |= ATTR_EXECUTABLE;
+        }
+        if target.rsplit('/').next().is_some_and(|name| name.starts_with('.')) {
+            entry.attributes |= ATTR_HIDDEN;
+        }
+        Ok(())
+    }
+    
+    fn read_host(&self, path: &str, dest: &str) -> Result<(String, Vec<u8>)> {
         // read file
         let mut file_buffer = Vec::new();
         let mut file = match File::open(path) {
             Ok(f) => f,
-            Err(_) => {
-                println!("File not found.");
-                return;
-            }
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Err(MicroFsError::NotFound(path.to_string())),
+            Err(e) => return Err(MicroFsError::Io(e))
+        };
+        file.read_to_end(&mut file_buffer)?;
+        
+        let filename = match Path::new(path).file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => return Err(MicroFsError::NotFound(path.to_string()))
+        };
+        let target = match self.dir_entries(dest) {
+            Ok(_) => format!("{}/{}", dest.trim_end_matches('/'), filename),
+            Err(_) => dest.to_string()
         };
-        file.read_to_end(&mut file_buffer).expect("Something went wrong when reading the file !");
+        Ok((target, file_buffer))
+    }
+    
+    pub(crate) fn add_data(&mut self, target: &str, file_buffer: Vec<u8>, policy: AllocPolicy) -> Result<()> {
+        let (dir, name) = split_parent(target);
+        check_name(target, name)?;
+        if name.len() > self.sb.max_name_size() {
+            return Err(MicroFsError::NameTooLong(name.to_string()));
+        }
+        let size = self.sb.table_size(self.dir_entries(&dir)?) + self.sb.name_slots(name.len()) * self.sb.entry_size();
+        if split_path(&dir).is_empty() && size > self.entries_size() {
+            return Err(MicroFsError::DirectoryFull);
+        }
         
-        let mut entry = Entry::new(path);
+        let mut entry = Entry::new(name);
         entry.size = file_buffer.len() as u32;
         let entry_block_size = file_buffer.len() / (SECTOR_SIZE * self.sb.block_size as usize) + 1;
-        println!("Entry size (bytes) = {}", file_buffer.len());
-        println!("Entry size (sectors) = {}", file_buffer.len() / SECTOR_SIZE + 1);
-        println!("Entry size (blocks) = {}", entry_block_size);
         
-        let mut blocks = self.empty_blocks(&mut entry);
-        if blocks.len() < entry_block_size {
-            println!("\nFile too large for File System.");
-        } else {
-            self.new_entries.push(entry);
-            self.update_fat(&mut blocks, true);
+        let blocks = self.allocate(entry_block_size, policy)?;
+        entry.start = blocks[0] as u32;
+        self.update_fat(&blocks, true);
+        let pending = PendingWrite { start: entry.start, data: file_buffer };
+        // the parent directory may need a block of its own to hold the entry
+        if let Err(e) = self.insert_entry(&dir, entry) {
+            self.update_fat(&blocks, false);
+            return Err(e);
         }
+        self.pending.push(pending);
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
@@ -0,0 +1,64 @@
+use super::*;
+
+// how the blocks of a new chain are picked among the free ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AllocPolicy {
+    // the first free blocks in FAT order, wherever they are
+    #[default]
+    FirstFit,
+    // the smallest run of free blocks large enough, or the first free blocks if
+    // no run is
+    BestFit,
+    // the smallest run of free blocks large enough, failing if no run is
+    Contiguous
+}
+
+impl<D: BlockDevice> MicroFS<D> {
+    // runs of free data blocks as (first block, length), in FAT order. Blocks
+    // freed since the last save are left out unless `reuse` is set
+    pub fn free_runs(&self, reuse: bool) -> Vec<(usize, usize)> {
+        let mut runs = Vec::new();
+        let mut run: Option<(usize, usize)> = None;
+        for block in self.sb.data_start()..self.data_end() {
+            let free = self.fat.get(block) == FatEntry::Free && (reuse || self.disk_fat.get(block) == FatEntry::Free);
+            run = match (run, free) {
+                (Some((start, len)), true) => Some((start, len + 1)),
+                (None, true) => Some((block, 1)),
+                (Some(run), false) => {
+                    runs.push(run);
+                    None
+                },
+                (None, false) => None
+            };
+        }
+        runs.extend(run);
+        runs
+    }
+
+    pub fn largest_free_run(&self) -> usize {
+        self.free_runs(true).iter().map(|&(_, len)| len).max().unwrap_or(0)
+    }
+
+    // pick `count` free blocks following `policy`, the FAT is left untouched
+    pub fn allocate(&self, count: usize, policy: AllocPolicy) -> Result<Vec<usize>> {
+        let blocks = self.find_free_blocks(count);
+        if blocks.len() < count {
+            return Err(MicroFsError::NoSpace { needed: count, available: blocks.len() });
+        }
+        if policy == AllocPolicy::FirstFit {
+            return Ok(blocks);
+        }
+        // as with the first fit, blocks freed since the last save are only
+        // taken when nothing else will do
+        for &reuse in &[false, true] {
+            let best = self.free_runs(reuse).into_iter().filter(|&(_, len)| len >= count).min_by_key(|&(_, len)| len);
+            if let Some((start, _)) = best {
+                return Ok((start..start + count).collect());
+            }
+        }
+        match policy {
+            AllocPolicy::Contiguous => Err(MicroFsError::NotContiguous { needed: count, largest: self.largest_free_run() }),
+            _ => Ok(blocks)
+        }
+    }
+}
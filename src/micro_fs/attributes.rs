@@ -0,0 +1,31 @@
+use super::*;
+
+impl<D: BlockDevice> MicroFS<D> {
+    // set the modification time of `path` to `time`, or to the current time,
+    // creating an empty file if it does not exist
+    pub fn touch(&mut self, path: &str, time: Option<u64>) -> Result<()> {
+        if !self.sb.has_attributes() {
+            return Err(MicroFsError::Unsupported("directory entries without times"));
+        }
+        let time = time.unwrap_or_else(now);
+        match self.find(path) {
+            Ok(_) => (),
+            Err(MicroFsError::NotFound(_)) => {
+                self.add_data(path, Vec::new(), AllocPolicy::FirstFit)?;
+                self.find_mut(path)?.created = time;
+            },
+            Err(e) => return Err(e)
+        }
+        self.find_mut(path)?.modified = time;
+        Ok(())
+    }
+
+    // replace the ATTR_* flags of `path`
+    pub fn set_attributes(&mut self, path: &str, attributes: u8) -> Result<()> {
+        if !self.sb.has_attributes() {
+            return Err(MicroFsError::Unsupported("directory entries without attributes"));
+        }
+        self.find_mut(path)?.attributes = attributes;
+        Ok(())
+    }
+}
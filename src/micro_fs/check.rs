@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::fmt;
+use super::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    BadSignature(u16),
+    ReservedBlock { path: String, block: usize },
+    BrokenChain { path: String, block: usize },
+    Cycle { path: String, block: usize },
+    CrossLinked { path: String, block: usize },
+    SizeMismatch { path: String, blocks: usize, expected: usize },
+    BadName(String),
+    Orphan(usize)
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::BadSignature(signature) => write!(f, "Bad signature {:#06x} (expected {:#06x})", signature, MAGIC),
+            Problem::ReservedBlock { ref path, block } => write!(f, "{}: block {} is in the super block, FAT, root directory or journal", path, block),
+            Problem::BrokenChain { ref path, block } => write!(f, "{}: chain broken at block {}", path, block),
+            Problem::Cycle { ref path, block } => write!(f, "{}: chain loops back to block {}", path, block),
+            Problem::CrossLinked { ref path, block } => write!(f, "{}: block {} already belongs to another entry", path, block),
+            Problem::SizeMismatch { ref path, blocks, expected } => write!(f, "{}: {} block(s) in the chain, {} expected from its size", path, blocks, expected),
+            Problem::BadName(ref path) => write!(f, "{}: name is not valid UTF-8", path),
+            Problem::Orphan(block) => write!(f, "Block {} is allocated but belongs to no entry", block)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    pub files: usize,
+    pub directories: usize,
+    pub used_blocks: usize,
+    pub problems: Vec<Problem>
+}
+impl CheckReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl<D: BlockDevice> MicroFS<D> {
+    pub fn check(&mut self) -> Result<CheckReport> {
+        self.scan(false)
+    }
+
+    // fix everything `check` reports and write the image: chains are cut before
+    // their first bad block, entries left without a chain are dropped, sizes are
+    // made to agree with the chains and orphaned blocks are freed
+    pub fn repair(&mut self) -> Result<CheckReport> {
+        let report = self.scan(true)?;
+        if report.problems.iter().any(|p| matches!(*p, Problem::BadSignature(_))) {
+            let raw_sb = self.sb.to_bytes();
+            self.dev.write_sector(0, &raw_sb)?;
+        }
+        if !report.is_clean() {
+            self.save()?;
+        }
+        Ok(report)
+    }
+
+    fn scan(&mut self, repair: bool) -> Result<CheckReport> {
+        let mut report = CheckReport::default();
+        let mut raw_sb = [0; SECTOR_SIZE];
+        self.dev.read_sector(0, &mut raw_sb)?;
+        let signature = u16::from_le_bytes([raw_sb[510], raw_sb[511]]);
+        if signature != MAGIC {
+            report.problems.push(Problem::BadSignature(signature));
+        }
+
+        let mut claimed = vec![false; self.fat.len()];
+        let mut entries = mem::take(&mut self.entries);
+        let res = self.scan_entries(&mut entries, "", &mut claimed, &mut report, repair);
+        self.entries = entries;
+        res?;
+
+        for (block, &used) in claimed.iter().enumerate() {
+            if !used && self.fat.get(block) != FatEntry::Free {
+                report.problems.push(Problem::Orphan(block));
+                if repair {
+                    self.fat.set(block, FatEntry::Free);
+                }
+            }
+        }
+        report.used_blocks = (0..self.fat.len()).filter(|&block| self.fat.get(block) != FatEntry::Free).count();
+        Ok(report)
+    }
+
+    fn scan_entries(&mut self, entries: &mut Vec<Entry>, parent: &str, claimed: &mut [bool], report: &mut CheckReport, repair: bool) -> Result<()> {
+        let block_bytes = SECTOR_SIZE * self.sb.block_size as usize;
+        let mut kept = Vec::new();
+        for mut entry in mem::take(entries) {
+            let path = format!("{}/{}", parent, entry.filename());
+            if str::from_utf8(&entry.name).is_err() {
+                report.problems.push(Problem::BadName(path.clone()));
+                if repair {
+                    entry.name = valid_name(&entry.name);
+                }
+            }
+            let blocks = self.scan_chain(&entry, &path, claimed, report, repair);
+            if blocks.is_empty() {
+                // nothing of the entry can be trusted, not even its children
+                if !repair {
+                    kept.push(entry);
+                }
+                continue;
+            }
+            if entry.is_dir() {
+                report.directories += 1;
+                self.scan_entries(&mut entry.children, &path, claimed, report, repair)?;
+            } else {
+                report.files += 1;
+            }
+
+            // the size of a directory is the length of the table of its children
+            let size = if entry.is_dir() { self.sb.table_size(&entry.children) } else { entry.size as usize };
+            let expected = size / block_bytes + 1;
+            if blocks.len() != expected || size != entry.size as usize {
+                report.problems.push(Problem::SizeMismatch { path, blocks: blocks.len(), expected });
+                if repair {
+                    if entry.is_dir() || blocks.len() > expected {
+                        self.resize_chain(&mut entry, size as u32)?;
+                        for block in self.get_blocks(&entry)? {
+                            claimed[block] = true;
+                        }
+                    } else {
+                        entry.size = (blocks.len() * block_bytes - 1) as u32;
+                    }
+                }
+            }
+            kept.push(entry);
+        }
+        *entries = kept;
+        Ok(())
+    }
+
+    // walk the chain of `entry` up to its first bad block, which a repair turns
+    // into the end of the chain
+    fn scan_chain(&mut self, entry: &Entry, path: &str, claimed: &mut [bool], report: &mut CheckReport, repair: bool) -> Vec<usize> {
+        let data_start = self.sb.data_start();
+        let journal_start = self.sb.journal_start();
+        let mut blocks = Vec::new();
+        let mut visited = HashSet::new();
+        let mut block = entry.start as usize;
+        loop {
+            let problem = if block < data_start || (block >= journal_start && block < self.fat.len()) {
+                Some(Problem::ReservedBlock { path: path.to_string(), block })
+            } else if block >= self.fat.len() {
+                Some(Problem::BrokenChain { path: path.to_string(), block })
+            } else if visited.contains(&block) {
+                Some(Problem::Cycle { path: path.to_string(), block })
+            } else if claimed[block] {
+                Some(Problem::CrossLinked { path: path.to_string(), block })
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                report.problems.push(problem);
+                if let (true, Some(&last)) = (repair, blocks.last()) {
+                    self.fat.set(last, FatEntry::End);
+                }
+                break;
+            }
+            blocks.push(block);
+            visited.insert(block);
+            claimed[block] = true;
+            match self.fat.get(block) {
+                FatEntry::Next(next) => block = next,
+                FatEntry::End => break,
+                FatEntry::Free => {
+                    report.problems.push(Problem::BrokenChain { path: path.to_string(), block });
+                    if repair {
+                        self.fat.set(block, FatEntry::End);
+                    }
+                    break;
+                }
+            }
+        }
+        blocks
+    }
+}
+
+// `name` with '_' in place of every byte of its invalid UTF-8 sequences, the
+// length and so the entries it takes are kept
+fn valid_name(name: &[u8]) -> Vec<u8> {
+    let mut valid = Vec::new();
+    let mut rest = name;
+    while let Err(e) = str::from_utf8(rest) {
+        let bad = e.error_len().unwrap_or(rest.len() - e.valid_up_to());
+        valid.extend_from_slice(&rest[..e.valid_up_to()]);
+        valid.extend(std::iter::repeat_n(b'_', bad));
+        rest = &rest[e.valid_up_to() + bad..];
+    }
+    valid.extend_from_slice(rest);
+    valid
+}
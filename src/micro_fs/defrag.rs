@@ -0,0 +1,169 @@
+use super::*;
+
+// how scattered the chains and the free blocks of the image are
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FragStats {
+    // runs of consecutive blocks of every entry, directories with a trailing '/'
+    pub fragments: Vec<(String, usize)>,
+    pub free_blocks: usize,
+    pub largest_free_run: usize
+}
+
+impl FragStats {
+    // entries whose chain is not a single ascending run
+    pub fn fragmented(&self) -> usize {
+        self.fragments.iter().filter(|&&(_, fragments)| fragments > 1).count()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DefragReport {
+    pub before: FragStats,
+    pub after: FragStats,
+    pub moved_blocks: usize
+}
+
+// the indexes leading to an entry through the directory tree, and its blocks
+pub(crate) type Chain = (Vec<usize>, Vec<usize>);
+// the chain of a block and its index in it
+pub(crate) type Owner = Option<(usize, usize)>;
+
+fn fragments(blocks: &[usize]) -> usize {
+    1 + blocks.windows(2).filter(|pair| pair[1] != pair[0] + 1).count()
+}
+
+impl<D: BlockDevice> MicroFS<D> {
+    pub fn fragmentation(&self) -> Result<FragStats> {
+        let mut stats = FragStats {
+            fragments: Vec::new(),
+            free_blocks: self.free_blocks(),
+            largest_free_run: self.largest_free_run()
+        };
+        self.entry_fragments(&self.entries, "", &mut stats.fragments)?;
+        Ok(stats)
+    }
+
+    fn entry_fragments(&self, entries: &[Entry], parent: &str, fragments_of: &mut Vec<(String, usize)>) -> Result<()> {
+        for entry in entries {
+            let path = format!("{}/{}", parent, entry.filename());
+            let blocks = self.get_blocks(entry)?;
+            if entry.is_dir() {
+                fragments_of.push((path.clone() + "/", fragments(&blocks)));
+                self.entry_fragments(&entry.children, &path, fragments_of)?;
+            } else {
+                fragments_of.push((path, fragments(&blocks)));
+            }
+        }
+        Ok(())
+    }
+
+    // move the data blocks so that every chain is a single ascending run, the
+    // chains following each other from the start of the data area in the order
+    // of the directory tree. Changes not saved yet are saved first.
+    // A block is only ever copied to a block the saved image does not use and
+    // the new chains are committed by `save`, so an interrupted defrag leaves
+    // the image as of its last save, with the journal making each save atomic
+    pub fn defrag(&mut self) -> Result<DefragReport> {
+        self.save()?;
+        let before = self.fragmentation()?;
+
+        // the chains are placed in the order of the directory tree
+        let (mut chains, mut owners) = self.chains()?;
+        let mut moved_blocks = 0;
+        let mut target = self.sb.data_start();
+        for c in 0..chains.len() {
+            for k in 0..chains[c].1.len() {
+                // blocks allocated to no entry cannot be moved, they are left where they are
+                while owners[target].is_none() && self.fat.get(target) != FatEntry::Free {
+                    target += 1;
+                }
+                if chains[c].1[k] == target {
+                    target += 1;
+                    continue;
+                }
+                // the block in the way goes to the last free block, past the
+                // blocks still to be placed if possible
+                if let Some((c2, k2)) = owners[target] {
+                    let free = self.disk_free_block(target)?;
+                    self.move_block(&mut chains, &mut owners, (c2, k2), free)?;
+                    moved_blocks += 1;
+                }
+                if self.disk_fat.get(target) != FatEntry::Free {
+                    self.save()?;
+                }
+                self.move_block(&mut chains, &mut owners, (c, k), target)?;
+                moved_blocks += 1;
+                target += 1;
+            }
+        }
+        self.save()?;
+        Ok(DefragReport { before, after: self.fragmentation()?, moved_blocks })
+    }
+
+    // the chain of every entry in the order of the directory tree, and the
+    // chain and the index in it of every block
+    pub(crate) fn chains(&self) -> Result<(Vec<Chain>, Vec<Owner>)> {
+        let mut chains = Vec::new();
+        self.collect_chains(&self.entries, &mut Vec::new(), &mut chains)?;
+        let mut owners = vec![None; self.fat.len()];
+        for (c, (_, blocks)) in chains.iter().enumerate() {
+            for (k, &block) in blocks.iter().enumerate() {
+                if owners[block].is_some() {
+                    return Err(MicroFsError::CorruptChain(block));
+                }
+                owners[block] = Some((c, k));
+            }
+        }
+        Ok((chains, owners))
+    }
+
+    fn collect_chains(&self, entries: &[Entry], id: &mut Vec<usize>, chains: &mut Vec<Chain>) -> Result<()> {
+        for (i, entry) in entries.iter().enumerate() {
+            id.push(i);
+            chains.push((id.clone(), self.get_blocks(entry)?));
+            self.collect_chains(&entry.children, id, chains)?;
+            id.pop();
+        }
+        Ok(())
+    }
+
+    // the last block free both in memory and in the saved image, saving first
+    // if only blocks freed since the last save are left
+    fn disk_free_block(&mut self, exclude: usize) -> Result<usize> {
+        for _ in 0..2 {
+            let free = (self.sb.data_start()..self.data_end()).rev()
+                .find(|&block| block != exclude && self.fat.get(block) == FatEntry::Free && self.disk_fat.get(block) == FatEntry::Free);
+            if let Some(block) = free {
+                return Ok(block);
+            }
+            self.save()?;
+        }
+        Err(MicroFsError::NoSpace { needed: 1, available: 0 })
+    }
+
+    // copy block `k` of chain `c` to the free block `dest` and link it in its place
+    pub(crate) fn move_block(&mut self, chains: &mut [Chain], owners: &mut [Owner], (c, k): (usize, usize), dest: usize) -> Result<()> {
+        let block_bytes = SECTOR_SIZE * self.sb.block_size as usize;
+        let src = chains[c].1[k];
+        let mut data = vec![0; block_bytes];
+        self.dev.read_bytes((src * block_bytes) as u64, &mut data)?;
+        self.dev.write_bytes((dest * block_bytes) as u64, &data)?;
+
+        let next = self.fat.get(src);
+        self.fat.set(dest, next);
+        self.fat.set(src, FatEntry::Free);
+        if k == 0 {
+            let mut entries = &mut self.entries;
+            let (last, parents) = chains[c].0.split_last().unwrap();
+            for &i in parents {
+                entries = &mut entries[i].children;
+            }
+            entries[*last].start = dest as u32;
+        } else {
+            self.fat.set(chains[c].1[k - 1], FatEntry::Next(dest));
+        }
+        chains[c].1[k] = dest;
+        owners[dest] = owners[src].take();
+        Ok(())
+    }
+}
@@ -1,20 +1,17 @@
 use super::*;
 
-impl MicroFS {
-    pub fn del(&mut self, filename: &str) {
-        for entry in self.entries.clone() {
-            if bytes_to_str(&entry.name) == filename.clone() {
-                let mut blocks = self.get_blocks(&mut entry.clone());
-                self.update_fat(&mut blocks, false);
-            }
+impl<D: BlockDevice> MicroFS<D> {
+    pub fn del(&mut self, filename: &str) -> Result<()> {
+        let entry = self.find(filename)?;
+        if entry.is_dir() {
+            return Err(MicroFsError::IsADirectory(filename.to_string()));
         }
-        self.entries.retain(|e| bytes_to_str(&e.name) != filename.clone()); 
-        for entry in self.new_entries.clone() {
-            if bytes_to_str(&entry.name) == filename.clone() {
-                let mut blocks = self.get_blocks(&mut entry.clone());
-                self.update_fat(&mut blocks, false);
-            }
+        if entry.is_read_only() {
+            return Err(MicroFsError::ReadOnly(filename.to_string()));
         }
-        self.new_entries.retain(|e| bytes_to_str(&e.name) != filename.clone()); 
+        for entry in self.remove_entries(filename, false)? {
+            self.free_entry(&entry)?;
+        }
+        Ok(())
     }
-}
\ No newline at end of file
+}
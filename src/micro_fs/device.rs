@@ -0,0 +1,189 @@
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::fs::File;
+use std::fs::OpenOptions;
+
+use super::SECTOR_SIZE;
+
+pub trait BlockDevice {
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()>;
+    fn write_sector(&mut self, sector: u64, buf: &[u8; SECTOR_SIZE]) -> io::Result<()>;
+    fn sector_count(&self) -> u64;
+    fn flush(&mut self) -> io::Result<()>;
+
+    // grow or truncate the device, which only images support
+    fn set_sector_count(&mut self, sectors: u64) -> io::Result<()> {
+        let _ = sectors;
+        Err(io::Error::new(io::ErrorKind::Unsupported, "the device cannot be resized"))
+    }
+
+    fn read_bytes(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let mut sector = [0; SECTOR_SIZE];
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let in_sector = (pos % SECTOR_SIZE as u64) as usize;
+            let len = cmp::min(SECTOR_SIZE - in_sector, buf.len() - done);
+            self.read_sector(pos / SECTOR_SIZE as u64, &mut sector)?;
+            buf[done..done + len].copy_from_slice(&sector[in_sector..in_sector + len]);
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let mut sector = [0; SECTOR_SIZE];
+        let mut done = 0;
+        while done < data.len() {
+            let pos = offset + done as u64;
+            let in_sector = (pos % SECTOR_SIZE as u64) as usize;
+            let len = cmp::min(SECTOR_SIZE - in_sector, data.len() - done);
+            // partial sectors are read back so the bytes around them are kept
+            if len < SECTOR_SIZE {
+                self.read_sector(pos / SECTOR_SIZE as u64, &mut sector)?;
+            }
+            sector[in_sector..in_sector + len].copy_from_slice(&data[done..done + len]);
+            self.write_sector(pos / SECTOR_SIZE as u64, &sector)?;
+            done += len;
+        }
+        Ok(())
+    }
+}
+
+fn out_of_range(sector: u64) -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, format!("sector {} is out of the device", sector))
+}
+
+#[derive(Debug)]
+pub struct FileDevice {
+    file: File,
+    sectors: u64
+}
+impl FileDevice {
+    pub fn open(path: &str) -> io::Result<FileDevice> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        // seeking to the end also works for raw block devices, whose metadata length is 0
+        let len = file.seek(SeekFrom::End(0))?;
+        Ok(FileDevice { file, sectors: len / SECTOR_SIZE as u64 })
+    }
+
+    pub fn create(path: &str, sectors: u64) -> io::Result<FileDevice> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        file.set_len(sectors * SECTOR_SIZE as u64)?;
+        Ok(FileDevice { file, sectors })
+    }
+}
+impl BlockDevice for FileDevice {
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()> {
+        if sector >= self.sectors {
+            return Err(out_of_range(sector));
+        }
+        self.file.seek(SeekFrom::Start(sector * SECTOR_SIZE as u64))?;
+        self.file.read_exact(buf)
+    }
+
+    fn write_sector(&mut self, sector: u64, buf: &[u8; SECTOR_SIZE]) -> io::Result<()> {
+        if sector >= self.sectors {
+            return Err(out_of_range(sector));
+        }
+        self.file.seek(SeekFrom::Start(sector * SECTOR_SIZE as u64))?;
+        self.file.write_all(buf)
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn set_sector_count(&mut self, sectors: u64) -> io::Result<()> {
+        self.file.set_len(sectors * SECTOR_SIZE as u64)?;
+        self.sectors = sectors;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MemDevice {
+    data: Vec<u8>
+}
+impl MemDevice {
+    pub fn new(sectors: usize) -> MemDevice {
+        MemDevice { data: vec![0; sectors * SECTOR_SIZE] }
+    }
+
+    pub fn from_vec(data: Vec<u8>) -> MemDevice {
+        MemDevice { data }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+}
+impl BlockDevice for MemDevice {
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()> {
+        SliceDevice::new(&mut self.data).read_sector(sector, buf)
+    }
+
+    fn write_sector(&mut self, sector: u64, buf: &[u8; SECTOR_SIZE]) -> io::Result<()> {
+        SliceDevice::new(&mut self.data).write_sector(sector, buf)
+    }
+
+    fn sector_count(&self) -> u64 {
+        (self.data.len() / SECTOR_SIZE) as u64
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_sector_count(&mut self, sectors: u64) -> io::Result<()> {
+        self.data.resize(sectors as usize * SECTOR_SIZE, 0);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct SliceDevice<'a> {
+    data: &'a mut [u8]
+}
+impl<'a> SliceDevice<'a> {
+    pub fn new(data: &'a mut [u8]) -> SliceDevice<'a> {
+        SliceDevice { data }
+    }
+}
+impl<'a> BlockDevice for SliceDevice<'a> {
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()> {
+        if sector >= self.sector_count() {
+            return Err(out_of_range(sector));
+        }
+        let start = sector as usize * SECTOR_SIZE;
+        buf.copy_from_slice(&self.data[start..start + SECTOR_SIZE]);
+        Ok(())
+    }
+
+    fn write_sector(&mut self, sector: u64, buf: &[u8; SECTOR_SIZE]) -> io::Result<()> {
+        if sector >= self.sector_count() {
+            return Err(out_of_range(sector));
+        }
+        let start = sector as usize * SECTOR_SIZE;
+        self.data[start..start + SECTOR_SIZE].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn sector_count(&self) -> u64 {
+        (self.data.len() / SECTOR_SIZE) as u64
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
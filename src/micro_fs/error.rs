@@ -0,0 +1,80 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+#[derive(Debug)]
+pub enum MicroFsError {
+    Io(io::Error),
+    BadSignature(u16),
+    BadSuperBlock(&'static str),
+    NoSpace { needed: usize, available: usize },
+    NotContiguous { needed: usize, largest: usize },
+    NotFound(String),
+    NameTooLong(String),
+    InvalidName(String),
+    DuplicateName(String),
+    DirectoryFull,
+    NotADirectory(String),
+    IsADirectory(String),
+    DirectoryNotEmpty(String),
+    InvalidMove(String),
+    JournalFull { needed: usize, available: usize },
+    CorruptChain(usize),
+    ReadOnly(String),
+    Unsupported(&'static str)
+}
+
+pub type Result<T> = result::Result<T, MicroFsError>;
+
+impl fmt::Display for MicroFsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MicroFsError::Io(ref err) => write!(f, "I/O error: {}", err),
+            MicroFsError::BadSignature(signature) => write!(f, "Bad signature {:#06x} (expected {:#06x})", signature, super::MAGIC),
+            MicroFsError::BadSuperBlock(reason) => write!(f, "Bad super block: {}", reason),
+            MicroFsError::NoSpace { needed, available } => write!(f, "Not enough space: {} block(s) needed, {} available", needed, available),
+            MicroFsError::NotContiguous { needed, largest } => write!(f, "No run of {} free block(s), the largest has {}", needed, largest),
+            MicroFsError::NotFound(ref name) => write!(f, "{}: not found", name),
+            MicroFsError::NameTooLong(ref name) => write!(f, "{}: name too long", name),
+            MicroFsError::InvalidName(ref name) => write!(f, "{}: invalid name", name),
+            MicroFsError::DuplicateName(ref name) => write!(f, "{}: name already exists", name),
+            MicroFsError::DirectoryFull => write!(f, "Root directory is full"),
+            MicroFsError::NotADirectory(ref name) => write!(f, "{}: not a directory", name),
+            MicroFsError::IsADirectory(ref name) => write!(f, "{}: is a directory", name),
+            MicroFsError::DirectoryNotEmpty(ref name) => write!(f, "{}: directory not empty", name),
+            MicroFsError::InvalidMove(ref name) => write!(f, "{}: cannot move a directory inside itself", name),
+            MicroFsError::JournalFull { needed, available } => write!(f, "Journal too small: {} sector(s) needed, {} available", needed, available),
+            MicroFsError::CorruptChain(block) => write!(f, "Corrupt FAT chain at block {}", block),
+            MicroFsError::ReadOnly(ref name) => write!(f, "{}: file is read only", name),
+            MicroFsError::Unsupported(reason) => write!(f, "Not supported by this image: {}", reason)
+        }
+    }
+}
+
+impl error::Error for MicroFsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            MicroFsError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for MicroFsError {
+    fn from(err: io::Error) -> MicroFsError {
+        MicroFsError::Io(err)
+    }
+}
+
+// for the `std::io` traits of `FileHandle`
+impl From<MicroFsError> for io::Error {
+    fn from(err: MicroFsError) -> io::Error {
+        match err {
+            MicroFsError::Io(err) => err,
+            MicroFsError::NotFound(_) => io::Error::new(io::ErrorKind::NotFound, err),
+            MicroFsError::ReadOnly(_) => io::Error::new(io::ErrorKind::PermissionDenied, err),
+            err => io::Error::other(err)
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs;
+use std::path::{Component, Path};
+use super::*;
+
+const TAR_BLOCK: usize = 512;
+
+// the name of `entry`, which a damaged or crafted image could make climb out of
+// the destination, as a single component of a host path
+fn host_name<'a>(entry: &'a Entry, path: &str) -> Result<&'a str> {
+    let name = entry.filename();
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(component)), None) if component == name && !name.contains('\0') => Ok(name),
+        _ => Err(MicroFsError::InvalidName(path.to_string()))
+    }
+}
+
+// the times and permissions of a member
+#[derive(Clone, Copy)]
+struct Stat {
+    mode: u32,
+    mtime: u64
+}
+
+// header of a POSIX (ustar) tar member of type `typeflag`, its path being
+// `prefix` and `name` joined by a '/'
+fn tar_header(prefix: &str, name: &str, size: usize, typeflag: u8, stat: Stat) -> [u8; TAR_BLOCK] {
+    let mut header = [0; TAR_BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..107].copy_from_slice(format!("{:07o}", stat.mode).as_bytes());
+    header[108..115].copy_from_slice(b"0000000");
+    header[116..123].copy_from_slice(b"0000000");
+    header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+    // the field holds 11 octal digits, up to the year 2242
+    header[136..147].copy_from_slice(format!("{:011o}", cmp::min(stat.mtime, 0o77777777777)).as_bytes());
+    header[156] = typeflag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    // the checksum is computed with its own field filled with spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|&byte| byte as u32).sum();
+    header[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+    header
+}
+
+// the header of the member at `path`, relative to the archive root. Paths
+// longer than the 100 bytes of the name field are split on a '/' between the
+// 155 bytes of the prefix and the name, or else given in full by a PAX
+// extended header placed before it
+fn tar_headers(path: &str, size: usize, dir: bool, stat: Stat) -> Vec<u8> {
+    let typeflag = if dir { b'5' } else { b'0' };
+    if path.len() <= 100 {
+        return tar_header("", path, size, typeflag, stat).to_vec();
+    }
+    let trimmed = path.trim_end_matches('/');
+    if let Some(i) = trimmed.char_indices().rev().find(|&(i, c)| c == '/' && i <= 155 && path.len() - i - 1 <= 100).map(|(i, _)| i) {
+        return tar_header(&path[..i], &path[i + 1..], size, typeflag, stat).to_vec();
+    }
+    // the record starts with its own length in decimal
+    let body = format!(" path={}\n", path);
+    let mut len = body.len();
+    while len.to_string().len() + body.len() != len {
+        len = len.to_string().len() + body.len();
+    }
+    let mut headers = tar_header("", "PaxHeader", len, b'x', Stat { mode: 0o644, ..stat }).to_vec();
+    headers.extend_from_slice(format!("{}{}", len, body).as_bytes());
+    headers.resize(headers.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+    // readers without PAX support get the path cut to the name field
+    let mut end = 100;
+    while !path.is_char_boundary(end) {
+        end -= 1;
+    }
+    headers.extend_from_slice(&tar_header("", &path[..end], size, typeflag, stat));
+    headers
+}
+
+impl<D: BlockDevice> MicroFS<D> {
+    // copy every file and directory of the image under the host directory
+    // `dest`, created if needed, and return the number of files written
+    pub fn export_dir(&mut self, dest: &str) -> Result<usize> {
+        fs::create_dir_all(dest)?;
+        let entries = self.entries.clone();
+        self.export_entries(&entries, "", Path::new(dest))
+    }
+
+    fn export_entries(&mut self, entries: &[Entry], parent: &str, dest: &Path) -> Result<usize> {
+        let mut files = 0;
+        for entry in entries {
+            let path = format!("{}/{}", parent, entry.filename());
+            let host = dest.join(host_name(entry, &path)?);
+            if entry.is_dir() {
+                fs::create_dir_all(&host)?;
+                files += self.export_entries(&entry.children, &path, &host)?;
+            } else {
+                let mut file = File::create(&host)?;
+                self.extract(&path, &mut file)?;
+                files += 1;
+            }
+        }
+        Ok(files)
+    }
+
+    // write the whole image as a POSIX tar archive to `out` and return the
+    // number of files written
+    pub fn export_tar<W: Write>(&mut self, out: &mut W) -> Result<usize> {
+        let entries = self.entries.clone();
+        let files = self.tar_entries(&entries, "", out)?;
+        // the archive ends with two empty blocks
+        out.write_all(&[0; 2 * TAR_BLOCK])?;
+        out.flush()?;
+        Ok(files)
+    }
+
+    fn tar_entries<W: Write>(&mut self, entries: &[Entry], parent: &str, out: &mut W) -> Result<usize> {
+        let mut files = 0;
+        for entry in entries {
+            let path = format!("{}{}", parent, host_name(entry, &format!("/{}{}", parent, entry.filename()))?);
+            let stat = self.tar_stat(entry);
+            if entry.is_dir() {
+                let path = path + "/";
+                out.write_all(&tar_headers(&path, 0, true, stat))?;
+                files += self.tar_entries(&entry.children, &path, out)?;
+            } else {
+                let mut data = Vec::new();
+                self.extract(&format!("/{}", path), &mut data)?;
+                out.write_all(&tar_headers(&path, data.len(), false, stat))?;
+                // the data is padded to a whole number of blocks
+                let padding = (TAR_BLOCK - data.len() % TAR_BLOCK) % TAR_BLOCK;
+                data.resize(data.len() + padding, 0);
+                out.write_all(&data)?;
+                files += 1;
+            }
+        }
+        Ok(files)
+    }
+    // the modification time and attributes of `entry`, on images keeping them
+    fn tar_stat(&self, entry: &Entry) -> Stat {
+        let mut mode = if entry.is_dir() || entry.attributes & ATTR_EXECUTABLE != 0 { 0o755 } else { 0o644 };
+        if entry.is_read_only() {
+            mode &= !0o222;
+        }
+        let mtime = if self.sb.has_attributes() { entry.modified } else { 0 };
+        Stat { mode, mtime }
+    }
+}
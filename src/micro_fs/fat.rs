@@ -0,0 +1,73 @@
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FatEntry {
+    Free,
+    End,
+    Next(usize)
+}
+
+// FAT entries are stored as little endian integers of `width` bytes, 0 ends a
+// chain and all bits set marks a free block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fat {
+    width: usize,
+    raw: Vec<u8>
+}
+impl Fat {
+    pub fn new(width: usize, blocks: usize) -> Fat {
+        Fat { width, raw: vec![0xff; width * blocks] }
+    }
+
+    pub fn from_bytes(width: usize, raw: Vec<u8>) -> Fat {
+        Fat { width, raw }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn len(&self) -> usize {
+        self.raw.len() / self.width
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+
+    pub fn max_block(&self) -> usize {
+        self.free_value() as usize - 1
+    }
+
+    pub fn get(&self, block: usize) -> FatEntry {
+        let mut value = 0u32;
+        for (i, byte) in self.raw[block * self.width..(block + 1) * self.width].iter().enumerate() {
+            value |= (*byte as u32) << (8 * i);
+        }
+        if value == 0 {
+            FatEntry::End
+        } else if value == self.free_value() {
+            FatEntry::Free
+        } else {
+            FatEntry::Next(value as usize)
+        }
+    }
+
+    pub fn set(&mut self, block: usize, entry: FatEntry) {
+        let value = match entry {
+            FatEntry::Free => self.free_value(),
+            FatEntry::End => 0,
+            FatEntry::Next(next) => next as u32
+        };
+        let width = self.width;
+        for (i, byte) in self.raw[block * width..(block + 1) * width].iter_mut().enumerate() {
+            *byte = (value >> (8 * i)) as u8;
+        }
+    }
+
+    fn free_value(&self) -> u32 {
+        (((1u64) << (8 * self.width)) - 1) as u32
+    }
+}
@@ -0,0 +1,295 @@
+use std::cmp;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    // read only, writes fail
+    Read,
+    // read and write an existing file
+    Write,
+    // like `Write`, creating an empty file if it does not exist
+    Create
+}
+
+// a file opened in the image, its data is read and written in place block by
+// block, new blocks are taken from the FAT when writing past the end of the
+// chain and the size of the entry is updated on `flush` or when the handle is
+// dropped. Like every other change, the FAT and directory entries only reach
+// the image with `MicroFS::save`
+pub struct FileHandle<'a, D: BlockDevice + 'a> {
+    fs: &'a mut MicroFS<D>,
+    path: String,
+    mode: OpenMode,
+    pos: u64,
+    size: u64,
+    // the part of the chain walked so far
+    blocks: Vec<usize>,
+    complete: bool,
+    // the modification time and the checksum are updated on `flush` once the
+    // file was written to
+    written: bool,
+    // given away by `detach`, there is nothing left to flush
+    detached: bool
+}
+
+// a handle taken off the image by `FileHandle::detach`, to be kept between
+// calls that each borrow the image and given back to `MicroFS::attach`
+pub struct OpenFile {
+    pub path: String,
+    mode: OpenMode,
+    pos: u64,
+    size: u64,
+    blocks: Vec<usize>,
+    complete: bool,
+    written: bool
+}
+
+impl<D: BlockDevice> MicroFS<D> {
+    pub fn open_file(&mut self, path: &str, mode: OpenMode) -> Result<FileHandle<'_, D>> {
+        let entry = match self.find(path) {
+            Ok(entry) => entry.clone(),
+            Err(MicroFsError::NotFound(_)) if mode == OpenMode::Create => {
+                self.add_data(path, Vec::new(), AllocPolicy::FirstFit)?;
+                self.find(path)?.clone()
+            },
+            Err(e) => return Err(e)
+        };
+        if entry.is_dir() {
+            return Err(MicroFsError::IsADirectory(path.to_string()));
+        }
+        if mode != OpenMode::Read && entry.is_read_only() {
+            return Err(MicroFsError::ReadOnly(path.to_string()));
+        }
+        // data added since the last save goes to its blocks first, so that the
+        // handle only has to deal with the image
+        if let Some(i) = self.pending.iter().position(|p| p.start == entry.start) {
+            let pending = self.pending.remove(i);
+            let blocks = self.get_blocks(&entry)?;
+            self.write_data(&blocks, &pending.data)?;
+            if self.sb.has_checksums() {
+                self.find_mut(path)?.checksum = crc32(0, &pending.data);
+            }
+        }
+        Ok(FileHandle {
+            fs: self,
+            path: path.to_string(),
+            mode,
+            pos: 0,
+            size: entry.size as u64,
+            blocks: vec![entry.start as usize],
+            complete: false,
+            written: false,
+            detached: false
+        })
+    }
+
+    // open a detached handle again, the chain it walked is kept unless the
+    // file was resized or replaced in the meantime
+    pub fn attach(&mut self, file: OpenFile) -> Result<FileHandle<'_, D>> {
+        let mut handle = self.open_file(&file.path, file.mode)?;
+        if handle.blocks[0] == file.blocks[0] && handle.size == file.size {
+            handle.blocks = file.blocks;
+            handle.complete = file.complete;
+        }
+        handle.pos = file.pos;
+        handle.written = file.written;
+        Ok(handle)
+    }
+}
+
+impl<'a, D: BlockDevice> FileHandle<'a, D> {
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    // give the image back without flushing: the size reaches the entry, the
+    // modification time and the checksum wait for the `flush` of the handle
+    // it is attached to again
+    pub fn detach(mut self) -> Result<OpenFile> {
+        if self.mode != OpenMode::Read {
+            self.fs.find_mut(&self.path)?.size = self.size as u32;
+        }
+        self.detached = true;
+        Ok(OpenFile {
+            path: mem::take(&mut self.path),
+            mode: self.mode,
+            pos: self.pos,
+            size: self.size,
+            blocks: mem::take(&mut self.blocks),
+            complete: self.complete,
+            written: self.written
+        })
+    }
+
+    // shrink the file, freeing the blocks it no longer needs, or grow it with zeros
+    pub fn set_len(&mut self, size: u64) -> Result<()> {
+        if self.mode == OpenMode::Read {
+            return Err(MicroFsError::Io(io::Error::new(io::ErrorKind::PermissionDenied, format!("{}: opened read only", self.path))));
+        }
+        if size > u32::MAX as u64 {
+            return Err(MicroFsError::Io(io::Error::new(io::ErrorKind::InvalidInput, format!("{}: file too large", self.path))));
+        }
+        if size >= self.size {
+            let pos = self.pos;
+            self.fill(size)?;
+            self.pos = pos;
+            return Ok(());
+        }
+        let mut entry = Entry { start: self.blocks[0] as u32, size: self.size as u32, ..Entry::new("") };
+        self.fs.resize_chain(&mut entry, size as u32)?;
+        self.blocks.truncate(1);
+        self.complete = false;
+        self.size = size;
+        self.written = true;
+        Ok(())
+    }
+
+    // write zeros from the end of the file up to `end`
+    fn fill(&mut self, end: u64) -> Result<()> {
+        self.pos = self.size;
+        while self.pos < end {
+            let len = cmp::min(end - self.pos, SECTOR_SIZE as u64) as usize;
+            self.write_at(&[0; SECTOR_SIZE][..len])?;
+        }
+        Ok(())
+    }
+
+    fn block_bytes(&self) -> u64 {
+        (SECTOR_SIZE * self.fs.sb.block_size as usize) as u64
+    }
+
+    // block `index` of the chain, following the FAT only as far as needed
+    fn block(&mut self, index: usize) -> Result<Option<usize>> {
+        while self.blocks.len() <= index && !self.complete {
+            let last = self.blocks[self.blocks.len() - 1];
+            if last >= self.fs.fat.len() || self.blocks.len() > self.fs.fat.len() {
+                return Err(MicroFsError::CorruptChain(last));
+            }
+            match self.fs.fat.get(last) {
+                FatEntry::Next(next) => self.blocks.push(next),
+                FatEntry::End => self.complete = true,
+                FatEntry::Free => return Err(MicroFsError::CorruptChain(last))
+            }
+        }
+        Ok(self.blocks.get(index).cloned())
+    }
+
+    // make the chain long enough to hold `size` bytes
+    fn extend(&mut self, size: u64) -> Result<()> {
+        let needed = (size / self.block_bytes()) as usize + 1;
+        if self.block(needed - 1)?.is_some() {
+            return Ok(());
+        }
+        let extra = self.fs.find_free_blocks(needed - self.blocks.len());
+        if extra.len() < needed - self.blocks.len() {
+            return Err(MicroFsError::NoSpace { needed: needed - self.blocks.len(), available: extra.len() });
+        }
+        let mut chain = vec![self.blocks[self.blocks.len() - 1]];
+        chain.extend_from_slice(&extra);
+        self.fs.update_fat(&chain, true);
+        self.blocks.extend_from_slice(&extra);
+        Ok(())
+    }
+
+    fn write_at(&mut self, data: &[u8]) -> Result<usize> {
+        let block_bytes = self.block_bytes();
+        let offset = self.pos % block_bytes;
+        let len = cmp::min(data.len() as u64, block_bytes - offset) as usize;
+        self.extend(self.pos + len as u64)?;
+        let block = self.blocks[(self.pos / block_bytes) as usize];
+        self.fs.dev.write_bytes(block as u64 * block_bytes + offset, &data[..len])?;
+        self.pos += len as u64;
+        self.size = cmp::max(self.size, self.pos);
+        self.written = true;
+        Ok(len)
+    }
+}
+
+impl<'a, D: BlockDevice> Read for FileHandle<'a, D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let block_bytes = self.block_bytes();
+        let offset = self.pos % block_bytes;
+        let len = cmp::min(cmp::min(buf.len() as u64, block_bytes - offset), self.size - self.pos) as usize;
+        let block = match self.block((self.pos / block_bytes) as usize)? {
+            Some(block) => block,
+            None => return Err(MicroFsError::CorruptChain(self.blocks[self.blocks.len() - 1]).into())
+        };
+        self.fs.dev.read_bytes(block as u64 * block_bytes + offset, &mut buf[..len])?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<'a, D: BlockDevice> Write for FileHandle<'a, D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.mode == OpenMode::Read {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{}: opened read only", self.path)));
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pos + buf.len() as u64 > u32::MAX as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{}: file too large", self.path)));
+        }
+        // a write past the end leaves a hole that reads back as zeros
+        if self.pos > self.size {
+            let pos = self.pos;
+            self.fill(pos)?;
+        }
+        Ok(self.write_at(buf)?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.mode == OpenMode::Read {
+            return Ok(());
+        }
+        let checksums = self.fs.sb.has_checksums();
+        let entry = self.fs.find_mut(&self.path)?;
+        entry.size = self.size as u32;
+        if self.written {
+            entry.modified = now();
+            if checksums {
+                let entry = entry.clone();
+                let checksum = self.fs.file_checksum(&entry)?;
+                self.fs.find_mut(&self.path)?.checksum = checksum;
+            }
+            self.written = false;
+        }
+        self.fs.dev.flush()
+    }
+}
+
+impl<'a, D: BlockDevice> Seek for FileHandle<'a, D> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset)
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            },
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position"))
+        }
+    }
+}
+
+impl<'a, D: BlockDevice> Drop for FileHandle<'a, D> {
+    fn drop(&mut self) {
+        if self.detached {
+            return;
+        }
+        let _ = self.flush();
+    }
+}
@@ -0,0 +1,188 @@
+use std::fs;
+use std::io::ErrorKind;
+use super::*;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildOptions {
+    // size of the image in bytes, the smallest size able to hold the directory if None
+    pub size: Option<usize>,
+    // bytes left free on top of the contents of the directory
+    pub padding: usize,
+    // add the entries of every directory sorted by name instead of in the
+    // order the host lists them, so that the same tree gives the same image
+    pub sorted: bool,
+    // give the image directory entries with times and attributes
+    pub attributes: bool,
+    // let the image store names longer than the name field of an entry
+    pub long_names: bool,
+    // keep the CRC32 of every file in its entry
+    pub checksums: bool
+}
+
+impl BuildOptions {
+    // the version bits on top of the FAT version
+    fn flags(&self) -> u16 {
+        let mut flags = 0;
+        if self.attributes {
+            flags |= VERSION_ATTRIBUTES;
+        }
+        if self.long_names {
+            flags |= VERSION_LONG_NAMES;
+        }
+        if self.checksums {
+            flags |= VERSION_CHECKSUMS;
+        }
+        flags
+    }
+}
+
+// a file or directory of the host, with what it takes in the image
+struct HostEntry {
+    name: String,
+    path: String,
+    size: usize,
+    children: Option<Vec<HostEntry>>
+}
+
+// the tables of the directories take the entries `sb` gives their names
+fn scan(dir: &str, sorted: bool, sb: &SuperBlock) -> Result<Vec<HostEntry>> {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Err(MicroFsError::NotFound(dir.to_string())),
+        Err(e) => return Err(MicroFsError::Io(e))
+    };
+    let mut entries = Vec::new();
+    for host_entry in read_dir {
+        let host_entry = host_entry?;
+        let path = host_entry.path().to_string_lossy().into_owned();
+        let name = host_entry.file_name().to_string_lossy().into_owned();
+        // links are followed, anything else than a file or a directory is left out
+        let metadata = fs::metadata(&path)?;
+        if metadata.is_dir() {
+            let children = scan(&path, sorted, sb)?;
+            entries.push(HostEntry { name, path, size: table_size(&children, sb), children: Some(children) });
+        } else if metadata.is_file() {
+            entries.push(HostEntry { name, path, size: metadata.len() as usize, children: None });
+        }
+    }
+    if sorted {
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    Ok(entries)
+}
+
+fn table_size(entries: &[HostEntry], sb: &SuperBlock) -> usize {
+    entries.iter().map(|e| sb.name_slots(stored_len(e)) * sb.entry_size()).sum()
+}
+
+// the trailing '/' of a directory takes a byte of the name
+fn stored_len(entry: &HostEntry) -> usize {
+    entry.name.len() + if entry.children.is_some() { 1 } else { 0 }
+}
+
+// blocks taken by `entries` and everything below them
+fn data_blocks(entries: &[HostEntry], block_bytes: usize) -> usize {
+    entries.iter().map(|e| {
+        e.size / block_bytes + 1 + e.children.as_ref().map_or(0, |children| data_blocks(children, block_bytes))
+    }).sum()
+}
+
+fn check_names(entries: &[HostEntry], name_size: usize) -> Result<()> {
+    for entry in entries {
+        if stored_len(entry) > name_size {
+            return Err(MicroFsError::NameTooLong(entry.path.clone()));
+        }
+        if let Some(ref children) = entry.children {
+            check_names(children, name_size)?;
+        }
+    }
+    Ok(())
+}
+
+// the super block of an image of `size` bytes with a root directory large enough
+// for a table of `root_size` bytes, and the number of data blocks it can chain
+fn layout(label: &str, bs: u8, size: usize, root_size: usize, flags: u16) -> (SuperBlock, usize) {
+    let block_bytes = SECTOR_SIZE * bs as usize;
+    let mut sb = SuperBlock::new(label, bs, size);
+    sb.version |= flags;
+    sb.set_root_blocks(cmp::max(1, root_size.div_ceil(block_bytes)) as u32);
+    if sb.validate().is_err() {
+        return (sb, 0);
+    }
+    // blocks past the largest encodable index cannot be chained
+    let max_block = (1u64 << (8 * sb.fat_width())) as usize - 2;
+    let data_end = cmp::min(sb.journal_start(), max_block + 1);
+    (sb, data_end.saturating_sub(sb.data_start()))
+}
+
+// the smallest image of block size `bs` with `needed` data blocks
+fn min_layout(label: &str, bs: u8, needed: usize, root_size: usize, flags: u16) -> SuperBlock {
+    let block_bytes = SECTOR_SIZE * bs as usize;
+    let mut blocks = needed + 1;
+    loop {
+        let (sb, available) = layout(label, bs, blocks * block_bytes, root_size, flags);
+        if available >= needed && sb.validate().is_ok() {
+            return sb;
+        }
+        blocks += cmp::max(1, needed.saturating_sub(available));
+    }
+}
+
+impl MicroFS<FileDevice> {
+    // bytes of the smallest image of block size `bs` holding the contents of `dir`
+    pub fn min_size(dir: &str, bs: u8) -> Result<usize> {
+        if bs == 0 {
+            return Err(MicroFsError::BadSuperBlock("block size is zero"));
+        }
+        let sb = SuperBlock::new("", bs, 0);
+        let entries = scan(dir, false, &sb)?;
+        let needed = data_blocks(&entries, SECTOR_SIZE * bs as usize);
+        let sb = min_layout("", bs, needed, table_size(&entries, &sb), 0);
+        Ok(sb.blocks() * SECTOR_SIZE * bs as usize)
+    }
+
+    // create `image` and copy the tree under the host directory `dir` into it
+    pub fn from_dir(image: &str, dir: &str, label: &str, bs: u8, options: &BuildOptions) -> Result<MicroFS> {
+        if bs == 0 {
+            return Err(MicroFsError::BadSuperBlock("block size is zero"));
+        }
+        let block_bytes = SECTOR_SIZE * bs as usize;
+        // the entries a name takes only depend on the version bits
+        let mut sb = SuperBlock::new("", bs, 0);
+        sb.version |= options.flags();
+        let entries = scan(dir, options.sorted, &sb)?;
+        let root_size = table_size(&entries, &sb);
+        let needed = data_blocks(&entries, block_bytes) + options.padding.div_ceil(block_bytes);
+        let sb = match options.size {
+            Some(size) => {
+                let (sb, available) = layout(label, bs, size, root_size, options.flags());
+                sb.validate()?;
+                if available < needed {
+                    return Err(MicroFsError::NoSpace { needed, available });
+                }
+                sb
+            },
+            None => min_layout(label, bs, needed, root_size, options.flags())
+        };
+        check_names(&entries, sb.max_name_size())?;
+
+        let mut fs = MicroFS::format_with(image, sb)?;
+        fs.add_tree(&entries, "")?;
+        fs.save()?;
+        Ok(fs)
+    }
+
+    fn add_tree(&mut self, entries: &[HostEntry], parent: &str) -> Result<()> {
+        for entry in entries {
+            let path = format!("{}/{}", parent, entry.name);
+            match entry.children {
+                Some(ref children) => {
+                    self.mkdir(&path)?;
+                    self.add_tree(children, &path)?;
+                },
+                None => self.add_as(&entry.path, &path)?
+            }
+        }
+        Ok(())
+    }
+}
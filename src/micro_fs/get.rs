@@ -0,0 +1,28 @@
+use super::*;
+
+impl<D: BlockDevice> MicroFS<D> {
+    pub fn extract<W: Write>(&mut self, filename: &str, out: &mut W) -> Result<usize> {
+        let entry = self.find(filename)?.clone();
+        if entry.is_dir() {
+            return Err(MicroFsError::IsADirectory(filename.to_string()));
+        }
+        // the data of a file added since the last save is not in its blocks yet
+        let data = match self.pending.iter().find(|p| p.start == entry.start) {
+            Some(pending) => pending.data.clone(),
+            None => {
+                let blocks = self.get_blocks(&entry)?;
+                self.read_data(&blocks, entry.size as usize)?
+            }
+        };
+        out.write_all(&data)?;
+        Ok(data.len())
+    }
+
+    pub fn extract_to(&mut self, filename: &str, dest: &str) -> Result<usize> {
+        if self.find(filename)?.is_dir() {
+            return Err(MicroFsError::IsADirectory(filename.to_string()));
+        }
+        let mut file = File::create(dest)?;
+        self.extract(filename, &mut file)
+    }
+}
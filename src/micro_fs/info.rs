@@ -1,13 +1,47 @@
 use super::*;
 
-impl MicroFS {
-    pub fn info(&mut self) {
-        println!("\nFS info :");
-        println!("Label : {}", bytes_to_str(&self.sb.label));
-        println!("Version : {}", self.sb.version);
-        println!("Sector size : {} bytes", self.sb.sector_size);
-        println!("Block size : {} sector(s)", self.sb.block_size);
-        println!("FAT size : {} bytes", self.sb.fat_size);
-        println!("Root entry : {}", self.sb.root_entry);
+// what `info` reports of the image, sizes are in bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsInfo {
+    pub label: String,
+    pub version: u16,
+    pub fat_bits: usize,
+    pub sector_size: usize,
+    pub block_size: usize,
+    pub fat_size: usize,
+    pub root_entry: usize,
+    pub root_blocks: usize,
+    pub root_entries: usize,
+    pub journal_blocks: usize,
+    pub journal_start: usize,
+    // every block of the image, of which the data blocks are either used or free
+    pub total_blocks: usize,
+    pub data_blocks: usize,
+    pub used_blocks: usize,
+    pub free_blocks: usize
+}
+
+impl<D: BlockDevice> MicroFS<D> {
+    pub fn info(&self) -> FsInfo {
+        let sb = &self.sb;
+        let data_blocks = self.data_blocks();
+        let free_blocks = self.free_blocks();
+        FsInfo {
+            label: bytes_to_str(&sb.label).to_string(),
+            version: sb.version,
+            fat_bits: sb.fat_width() * 8,
+            sector_size: sb.sector_size as usize,
+            block_size: SECTOR_SIZE * sb.block_size as usize,
+            fat_size: sb.fat_size as usize,
+            root_entry: sb.root_entry as usize,
+            root_blocks: sb.root_blocks as usize,
+            root_entries: self.entries_size() / sb.entry_size(),
+            journal_blocks: sb.journal_blocks as usize,
+            journal_start: sb.journal_start(),
+            total_blocks: sb.blocks(),
+            data_blocks,
+            used_blocks: data_blocks - free_blocks,
+            free_blocks
+        }
     }
-}
\ No newline at end of file
+}
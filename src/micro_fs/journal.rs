@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use super::*;
+
+const JOURNAL_MAGIC: [u8; 4] = *b"MFSJ";
+
+// The journal starts with a header sector holding its magic, the number of
+// sectors it carries and the CRC32 of everything after the header. A table of
+// their target sectors (4 bytes each) follows, then the sectors themselves.
+impl<D: BlockDevice> MicroFS<D> {
+    // write `(offset, bytes)` pairs so that a failure never leaves a FAT and
+    // directories that disagree: file data is written in place first, then the
+    // sectors of the metadata (FAT and directory tables) that change are copied
+    // to the journal and only applied once its header is written, which an
+    // interrupted save leaves for `open` to replay
+    //
+    // data written over blocks the saved image still uses is not covered, which
+    // is why the allocator prefers blocks that are free in `disk_fat`. A journal
+    // too small for the metadata grows over the free blocks before it, or the
+    // commit is refused with nothing written
+    pub fn commit(&mut self, data: &[(u64, Vec<u8>)], metadata: &[(u64, Vec<u8>)]) -> Result<()> {
+        let mut sectors: BTreeMap<u64, ([u8; SECTOR_SIZE], [u8; SECTOR_SIZE])> = BTreeMap::new();
+        for &(offset, ref data) in metadata {
+            let mut done = 0;
+            while done < data.len() {
+                let pos = offset + done as u64;
+                let sector = pos / SECTOR_SIZE as u64;
+                let in_sector = (pos % SECTOR_SIZE as u64) as usize;
+                let len = cmp::min(SECTOR_SIZE - in_sector, data.len() - done);
+                let staged = match sectors.entry(sector) {
+                    btree_map::Entry::Occupied(staged) => staged.into_mut(),
+                    btree_map::Entry::Vacant(staged) => {
+                        let mut raw = [0; SECTOR_SIZE];
+                        self.dev.read_sector(sector, &mut raw)?;
+                        staged.insert((raw, raw))
+                    }
+                };
+                staged.1[in_sector..in_sector + len].copy_from_slice(&data[done..done + len]);
+                done += len;
+            }
+        }
+        
+        let journaled: Vec<(u64, [u8; SECTOR_SIZE])> = sectors.into_iter()
+            .filter(|&(_, (ref old, ref new))| old[..] != new[..])
+            .map(|(sector, (_, new))| (sector, new))
+            .collect();
+        let needed = 1 + (journaled.len() * 4).div_ceil(SECTOR_SIZE) + journaled.len();
+        let available = self.sb.journal_blocks as usize * self.sb.block_size as usize;
+        if self.sb.journal_blocks != 0 && needed > available {
+            self.grow_journal(needed)?;
+        }
+        
+        for &(offset, ref data) in data {
+            self.dev.write_bytes(offset, data)?;
+        }
+        if self.sb.journal_blocks == 0 {
+            for &(sector, ref raw) in &journaled {
+                self.dev.write_sector(sector, raw)?;
+            }
+            self.dev.flush()?;
+            return Ok(());
+        }
+        let start = self.sb.journal_start() as u64 * self.sb.block_size as u64;
+        self.write_transaction(start, &journaled)
+    }
+    
+    // extend the journal over the blocks before it up to `needed` sectors when
+    // they are free, in memory and on disk. The super block recording the new
+    // size is written on its own, a single sector write leaving either size
+    fn grow_journal(&mut self, needed: usize) -> Result<()> {
+        let bs = self.sb.block_size as usize;
+        let available = self.sb.journal_blocks as usize * bs;
+        let blocks = needed.div_ceil(bs);
+        let free = |fat: &Fat, block: usize| block < fat.len() && fat.get(block) == FatEntry::Free;
+        let fits = blocks + self.sb.data_start() <= self.sb.blocks()
+            && (self.sb.blocks() - blocks..self.sb.journal_start()).all(|block| free(&self.fat, block) && free(&self.disk_fat, block));
+        if !fits {
+            return Err(MicroFsError::JournalFull { needed, available });
+        }
+        let mut sb = self.sb;
+        sb.journal_blocks = blocks as u32;
+        // the blocks taken may hold anything, the header must not pass for a journal
+        self.dev.write_sector((sb.journal_start() * bs) as u64, &[0; SECTOR_SIZE])?;
+        self.dev.flush()?;
+        self.dev.write_sector(0, &sb.to_bytes())?;
+        self.dev.flush()?;
+        self.sb = sb;
+        Ok(())
+    }
+    
+    fn write_transaction(&mut self, start: u64, journaled: &[(u64, [u8; SECTOR_SIZE])]) -> Result<()> {
+        let table_sectors = (journaled.len() * 4).div_ceil(SECTOR_SIZE);
+        let mut table = Vec::new();
+        for &(sector, _) in journaled {
+            table.extend_from_slice(&(sector as u32).to_le_bytes());
+        }
+        table.resize(table_sectors * SECTOR_SIZE, 0);
+        self.dev.write_bytes((start + 1) * SECTOR_SIZE as u64, &table)?;
+        let mut crc = crc32(0, &table);
+        for (i, (_, raw)) in journaled.iter().enumerate() {
+            self.dev.write_sector(start + 1 + (table_sectors + i) as u64, raw)?;
+            crc = crc32(crc, raw);
+        }
+        self.dev.flush()?;
+        
+        let mut header = [0; SECTOR_SIZE];
+        header[0..4].copy_from_slice(&JOURNAL_MAGIC);
+        header[4..8].copy_from_slice(&(journaled.len() as u32).to_le_bytes());
+        header[8..12].copy_from_slice(&crc.to_le_bytes());
+        self.dev.write_sector(start, &header)?;
+        self.dev.flush()?;
+        
+        // committed, a failure from here on is repaired by the next open
+        self.apply_journal(start, journaled)
+    }
+    
+    // apply a committed journal left by an interrupted save, returns whether there was one
+    pub fn replay_journal(&mut self) -> Result<bool> {
+        if self.sb.journal_blocks == 0 {
+            return Ok(false);
+        }
+        let bs = self.sb.block_size as u64;
+        let start = self.sb.journal_start() as u64 * bs;
+        let mut header = [0; SECTOR_SIZE];
+        self.dev.read_sector(start, &mut header)?;
+        if header[0..4] != JOURNAL_MAGIC {
+            return Ok(false);
+        }
+        let count = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let crc = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        let table_sectors = (count * 4).div_ceil(SECTOR_SIZE);
+        let available = self.sb.journal_blocks as usize * self.sb.block_size as usize;
+        if 1 + table_sectors + count > available {
+            return self.discard_journal(start);
+        }
+        
+        let mut table = vec![0; table_sectors * SECTOR_SIZE];
+        self.dev.read_bytes((start + 1) * SECTOR_SIZE as u64, &mut table)?;
+        let mut journaled = Vec::new();
+        let mut sum = crc32(0, &table);
+        for i in 0..count {
+            let sector = u32::from_le_bytes([table[4 * i], table[4 * i + 1], table[4 * i + 2], table[4 * i + 3]]) as u64;
+            if sector >= self.dev.sector_count() {
+                return self.discard_journal(start);
+            }
+            let mut raw = [0; SECTOR_SIZE];
+            self.dev.read_sector(start + 1 + (table_sectors + i) as u64, &mut raw)?;
+            sum = crc32(sum, &raw);
+            journaled.push((sector, raw));
+        }
+        if sum != crc {
+            return self.discard_journal(start);
+        }
+        self.apply_journal(start, &journaled)?;
+        Ok(true)
+    }
+    
+    // a journal that does not check out was never committed and the image still
+    // holds the previous save
+    fn discard_journal(&mut self, start: u64) -> Result<bool> {
+        self.dev.write_sector(start, &[0; SECTOR_SIZE])?;
+        self.dev.flush()?;
+        Ok(false)
+    }
+    
+    fn apply_journal(&mut self, start: u64, journaled: &[(u64, [u8; SECTOR_SIZE])]) -> Result<()> {
+        // the super block says where the journal is, a new one (from a resize)
+        // only goes once everything else is in place
+        let (super_block, others): (Vec<_>, Vec<_>) = journaled.iter().partition(|&&(sector, _)| sector == 0);
+        for &&(sector, ref raw) in others.iter().chain(&super_block) {
+            self.dev.write_sector(sector, raw)?;
+        }
+        self.dev.flush()?;
+        self.dev.write_sector(start, &[0; SECTOR_SIZE])?;
+        self.dev.flush()?;
+        Ok(())
+    }
+}
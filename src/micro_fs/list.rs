@@ -1,15 +1,60 @@
 use super::*;
 
-impl MicroFS {
-    pub fn list(&mut self) {
-        println!("\nFiles :");
-        for entry in self.entries.clone() {
-            println!("{}", bytes_to_str(&entry.name));
-            println!("{} bytes\n", entry.size);
-        }
-        for entry in self.new_entries.clone() {
-            println!("{}", bytes_to_str(&entry.name));
-            println!("{} bytes\n", entry.size);
-        }
+// what `list` reports of an entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    // the name without the trailing '/' of directories
+    pub name: String,
+    pub path: String,
+    pub is_dir: bool,
+    // bytes of a file, or of the table of a directory
+    pub size: u32,
+    // entries of a directory, 0 for a file
+    pub children: usize,
+    pub start: u32,
+    // blocks of the chain, as implied by the size
+    pub blocks: usize,
+    // bytes of those blocks
+    pub allocated: usize,
+    // seconds since the Unix epoch and ATTR_* flags, 0 on images without
+    // VERSION_ATTRIBUTES
+    pub created: u64,
+    pub modified: u64,
+    pub attributes: u8
+}
+
+impl EntryInfo {
+    // bytes allocated past the end of the data
+    pub fn slack(&self) -> usize {
+        self.allocated - self.size as usize
+    }
+}
+
+impl<D: BlockDevice> MicroFS<D> {
+    // the entries of the directory at `path`, or the file at `path` itself
+    pub fn list(&self, path: &str) -> Result<Vec<EntryInfo>> {
+        let (dir, entries) = match self.dir_entries(path) {
+            Ok(entries) => (split_path(path).join("/"), entries.clone()),
+            Err(MicroFsError::NotADirectory(_)) => (split_parent(path).0, vec![self.find(path)?.clone()]),
+            Err(e) => return Err(e)
+        };
+        let block_bytes = SECTOR_SIZE * self.sb.block_size as usize;
+        let attributes = self.sb.has_attributes();
+        Ok(entries.iter().map(|entry| {
+            let name = entry.filename().to_string();
+            EntryInfo {
+                path: if dir.is_empty() { format!("/{}", name) } else { format!("/{}/{}", dir, name) },
+                name,
+                is_dir: entry.is_dir(),
+                size: entry.size,
+                children: entry.children.len(),
+                start: entry.start,
+                blocks: entry.size as usize / block_bytes + 1,
+                allocated: (entry.size as usize / block_bytes + 1) * block_bytes,
+                created: if attributes { entry.created } else { 0 },
+                modified: if attributes { entry.modified } else { 0 },
+                attributes: if attributes { entry.attributes } else { 0 }
+            }
+        }).collect())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,27 @@
+use super::*;
+
+impl<D: BlockDevice> MicroFS<D> {
+    pub fn mkdir(&mut self, path: &str) -> Result<()> {
+        let (dir, name) = split_parent(path);
+        check_name(path, name)?;
+        if self.dir_entries(&dir)?.iter().any(|e| e.filename() == name) {
+            return Err(MicroFsError::DuplicateName(path.to_string()));
+        }
+        // the trailing '/' marking a directory takes a byte of the name
+        if name.len() + 1 > self.sb.max_name_size() {
+            return Err(MicroFsError::NameTooLong(name.to_string()));
+        }
+        
+        let mut entry = Entry::new(&format!("{}/", name));
+        let blocks = self.empty_blocks(&mut entry);
+        if blocks.is_empty() {
+            return Err(MicroFsError::NoSpace { needed: 1, available: 0 });
+        }
+        self.update_fat(&blocks, true);
+        if let Err(e) = self.insert_entry(&dir, entry) {
+            self.update_fat(&blocks, false);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
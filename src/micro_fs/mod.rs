@@ -1,73 +1,164 @@
+use std::cmp;
 use std::mem;
 use std::str;
 use std::io::prelude::*;
-use std::io::SeekFrom;
-use std::fs;
 use std::fs::File;
-use std::fs::OpenOptions;
 
 pub mod utils;
 use self::utils::*;
 
+pub mod error;
+pub use self::error::*;
+
+pub mod fat;
+pub use self::fat::*;
+
+pub mod device;
+pub use self::device::*;
+
+pub mod check;
+pub use self::check::*;
+
+pub mod list;
+pub use self::list::*;
+
+pub mod info;
+pub use self::info::*;
+
+pub mod space;
+pub use self::space::*;
+
+pub mod allocator;
+pub use self::allocator::*;
+
+pub mod defrag;
+pub use self::defrag::*;
+
+pub mod file;
+pub use self::file::*;
+
+pub mod from_dir;
+pub use self::from_dir::*;
+
+pub mod verify;
+pub use self::verify::*;
+
+#[cfg(feature = "fuse")]
+pub mod mount;
+
 mod create;
 mod add;
 mod save;
 mod del;
-mod list;
-mod info;
+mod get;
+mod mkdir;
+mod rmdir;
+mod rename;
+mod export;
+mod resize;
+mod attributes;
+mod journal;
 
 pub const MAGIC: u16 = 0x55aa;
 pub const SECTOR_SIZE: usize = 0x200;
+pub const NAME_SIZE: usize = 26;
+pub const ENTRY_SIZE: usize = 32;
+pub const VERSION_FAT8: u16 = 1;
+pub const VERSION_FAT16: u16 = 2;
+pub const VERSION_FAT32: u16 = 3;
+// set on top of the FAT version by images whose directory entries are
+// EXTENDED_ENTRY_SIZE bytes long, the name, start and size being followed by
+// the creation and modification times (seconds since the Unix epoch, 8 bytes
+// each) and a byte of attributes
+pub const VERSION_ATTRIBUTES: u16 = 0x100;
+pub const EXTENDED_ENTRY_SIZE: usize = 64;
+// set by images keeping the CRC32 of every file in bytes 52 to 55 of its
+// entry, which is EXTENDED_ENTRY_SIZE bytes long as well
+pub const VERSION_CHECKSUMS: u16 = 0x400;
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_HIDDEN: u8 = 0x02;
+pub const ATTR_SYSTEM: u8 = 0x04;
+pub const ATTR_EXECUTABLE: u8 = 0x08;
+// set on top of the FAT version by images whose names can be longer than the
+// name field: the first INLINE_NAME_SIZE bytes stay in the entry and the rest
+// goes to continuation entries right after it, each starting with
+// LONG_NAME_MARKER and its rank from 1, followed by the next bytes of the name
+pub const VERSION_LONG_NAMES: u16 = 0x200;
+pub const LONG_NAME_SIZE: usize = 255;
+pub const LONG_NAME_MARKER: u8 = 0x01;
+// the name field of a 32-bit FAT, so that widening the FAT never changes the
+// number of entries a name takes
+pub const INLINE_NAME_SIZE: usize = NAME_SIZE - 2;
+// sectors of journal reserved on top of a copy of the FAT and the root directory,
+// for the header, the table of target sectors and a few directory blocks
+pub const JOURNAL_SLACK: usize = 5;
 
 #[derive(Debug)]
-pub struct MicroFS {
-    pub image: String,
+pub struct MicroFS<D: BlockDevice = FileDevice> {
+    pub dev: D,
     pub sb: SuperBlock,
-    pub fat: Vec<u8>,
+    pub fat: Fat,
+    // the FAT as of the last save
+    pub disk_fat: Fat,
     pub entries: Vec<Entry>,
-    pub new_entries: Vec<Entry>
+    pub pending: Vec<PendingWrite>
 }
-impl MicroFS {
-    pub fn new(image: &str) -> MicroFS {
-        unsafe {
-            if fs::metadata(image).is_ok() {
-                let mut raw_sb = [0; SECTOR_SIZE];
-                let mut file = File::open(image).expect("File not found !");
-                file.read(&mut raw_sb).expect("Something went wrong reading the file !");
-                let signature = mem::transmute::<[u8;2], u16>([raw_sb[510], raw_sb[511]]);
-                if signature == MAGIC {
-                    // get super block
-                    let bs = raw_sb[13];
-                    let raw_label: Vec<u8> = Vec::from(&(raw_sb[82..90]));
-                    let label = String::from_utf8(raw_label).unwrap();
-                    let size = fs::metadata(image.clone()).expect("Failed getting metadata!").len() as usize;
-                    let sb = SuperBlock::new(&label, bs, size);
-                    let mut fs = MicroFS {
-                        image: image.to_string(),
-                        sb: sb,
-                        fat: Vec::new(),
-                        entries: Vec::new(),
-                        new_entries: Vec::new()
-                    };
-                    fs.set_fat();
-                    fs.set_entries();
-                    println!("\n{} is a valid image. You can modify it using the menu.", image);
-                    return fs;
-                }
+impl MicroFS<FileDevice> {
+    pub fn open(image: &str) -> Result<MicroFS> {
+        MicroFS::open_device(FileDevice::open(image)?)
+    }
+    
+    pub fn open_unchecked(image: &str) -> Result<MicroFS> {
+        MicroFS::open_device_unchecked(FileDevice::open(image)?)
+    }
+}
+impl<D: BlockDevice> MicroFS<D> {
+    pub fn open_device(dev: D) -> Result<MicroFS<D>> {
+        MicroFS::load(dev, false)
+    }
+    
+    // accepts a bad signature and skips the directories that cannot be read so
+    // that `check` and `repair` get to see what is wrong with the image
+    pub fn open_device_unchecked(dev: D) -> Result<MicroFS<D>> {
+        MicroFS::load(dev, true)
+    }
+    
+    fn load(mut dev: D, lenient: bool) -> Result<MicroFS<D>> {
+        let mut raw_sb = [0; SECTOR_SIZE];
+        dev.read_sector(0, &mut raw_sb)?;
+        // get super block
+        let sb = match SuperBlock::from_bytes(&raw_sb) {
+            Err(MicroFsError::BadSignature(_)) if lenient => {
+                raw_sb[510..512].copy_from_slice(&MAGIC.to_le_bytes());
+                SuperBlock::from_bytes(&raw_sb)?
+            },
+            res => res?
+        };
+        if dev.sector_count() < (sb.blocks() * sb.block_size as usize) as u64 {
+            return Err(MicroFsError::BadSuperBlock("image is smaller than the size recorded in the super block"));
+        }
+        let mut fs = MicroFS {
+            dev,
+            sb,
+            fat: Fat::new(sb.fat_width(), 0),
+            disk_fat: Fat::new(sb.fat_width(), 0),
+            entries: Vec::new(),
+            pending: Vec::new()
+        };
+        // the journal of an interrupted resize carries a new super block
+        if fs.replay_journal()? {
+            fs.dev.read_sector(0, &mut raw_sb)?;
+            if let Ok(sb) = SuperBlock::from_bytes(&raw_sb) {
+                fs.sb = sb;
             }
-            println!("\n{} does not exist. You should create it first with the menu.", image);
-            return MicroFS {
-                image: image.to_string(),
-                sb: mem::uninitialized(),
-                fat: Vec::new(),
-                entries:  Vec::new(),
-                new_entries: Vec::new()
-            };
         }
+        fs.set_fat()?;
+        fs.set_entries(lenient)?;
+        Ok(fs)
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(C)]
 pub struct SuperBlock {
     pub sector_size: u16,
@@ -75,11 +166,26 @@ pub struct SuperBlock {
     pub fat_size: u32,
     pub version: u16,
     pub root_entry: u32,
+    pub root_blocks: u32,
+    pub journal_blocks: u32,
     pub label: [u8;8],
     pub signature: u16
 }
 impl SuperBlock {
     pub fn new(label: &str, bs: u8, size: usize) -> SuperBlock {
+        // pick the narrowest FAT able to address every block
+        let blocks = size / (SECTOR_SIZE * bs as usize);
+        let version = if blocks <= 0xff {
+            VERSION_FAT8
+        } else if blocks <= 0xffff {
+            VERSION_FAT16
+        } else {
+            VERSION_FAT32
+        };
+        SuperBlock::with_version(label, bs, size, version)
+    }
+    
+    pub fn with_version(label: &str, bs: u8, size: usize, version: u16) -> SuperBlock {
         let mut raw_label : [u8;8] = [0;8];
         let mut i = 0;
         for byte in label.bytes() {
@@ -87,43 +193,296 @@ impl SuperBlock {
             i += 1;
             if i == 8 { break; }
         }
-        let fat_size = size / (SECTOR_SIZE * bs as usize);
+        let fat_size = size / (SECTOR_SIZE * bs as usize) * fat_width(version);
         let mut root_entry = (SECTOR_SIZE + fat_size) / (SECTOR_SIZE * bs as usize);
-        if ((SECTOR_SIZE + fat_size) % (SECTOR_SIZE * bs as usize)) != 0 {
+        if !(SECTOR_SIZE + fat_size).is_multiple_of(SECTOR_SIZE * bs as usize) {
             root_entry += 1;
         }
-        SuperBlock {
+        let mut sb = SuperBlock {
             sector_size: SECTOR_SIZE as u16,
             block_size: bs,
             fat_size: fat_size as u32,
-            version: 1,
+            version,
             root_entry: root_entry as u32,
+            root_blocks: 1,
+            journal_blocks: 0,
             label: raw_label,
             signature: MAGIC
+        };
+        sb.journal_blocks = sb.default_journal_blocks();
+        sb
+    }
+    
+    pub fn from_bytes(raw_sb: &[u8; SECTOR_SIZE]) -> Result<SuperBlock> {
+        let sb = SuperBlock {
+            sector_size: u16::from_le_bytes([raw_sb[11], raw_sb[12]]),
+            block_size: raw_sb[13],
+            fat_size: u32::from_le_bytes([raw_sb[36], raw_sb[37], raw_sb[38], raw_sb[39]]),
+            version: u16::from_le_bytes([raw_sb[42], raw_sb[43]]),
+            root_entry: u32::from_le_bytes([raw_sb[44], raw_sb[45], raw_sb[46], raw_sb[47]]),
+            // images written before the root directory size was recorded store 0 and use one block
+            root_blocks: cmp::max(1, u32::from_le_bytes([raw_sb[48], raw_sb[49], raw_sb[50], raw_sb[51]])),
+            // and no journal, their saves are written in place
+            journal_blocks: u32::from_le_bytes([raw_sb[52], raw_sb[53], raw_sb[54], raw_sb[55]]),
+            label: [raw_sb[82], raw_sb[83], raw_sb[84], raw_sb[85], raw_sb[86], raw_sb[87], raw_sb[88], raw_sb[89]],
+            signature: u16::from_le_bytes([raw_sb[510], raw_sb[511]])
+        };
+        sb.validate()?;
+        Ok(sb)
+    }
+    
+    pub fn to_bytes(&self) -> [u8; SECTOR_SIZE] {
+        let mut raw_sb = [0; SECTOR_SIZE];
+        raw_sb[11..13].copy_from_slice(&self.sector_size.to_le_bytes());
+        raw_sb[13] = self.block_size;
+        raw_sb[36..40].copy_from_slice(&self.fat_size.to_le_bytes());
+        raw_sb[42..44].copy_from_slice(&self.version.to_le_bytes());
+        raw_sb[44..48].copy_from_slice(&self.root_entry.to_le_bytes());
+        raw_sb[48..52].copy_from_slice(&self.root_blocks.to_le_bytes());
+        raw_sb[52..56].copy_from_slice(&self.journal_blocks.to_le_bytes());
+        raw_sb[82..90].copy_from_slice(&self.label);
+        raw_sb[510..512].copy_from_slice(&self.signature.to_le_bytes());
+        raw_sb
+    }
+    
+    pub fn validate(&self) -> Result<()> {
+        if self.signature != MAGIC {
+            return Err(MicroFsError::BadSignature(self.signature));
+        }
+        if self.sector_size as usize != SECTOR_SIZE {
+            return Err(MicroFsError::BadSuperBlock("unsupported sector size"));
+        }
+        if fat_width(self.version) == 0 || self.version & !(VERSION_ATTRIBUTES | VERSION_LONG_NAMES | VERSION_CHECKSUMS | 0xff) != 0 {
+            return Err(MicroFsError::BadSuperBlock("unsupported version"));
+        }
+        if !(self.fat_size as usize).is_multiple_of(self.fat_width()) {
+            return Err(MicroFsError::BadSuperBlock("FAT size is not a whole number of entries"));
+        }
+        if self.block_size == 0 {
+            return Err(MicroFsError::BadSuperBlock("block size is zero"));
+        }
+        let block_bytes = SECTOR_SIZE * self.block_size as usize;
+        if self.root_entry as usize != (SECTOR_SIZE + self.fat_size as usize).div_ceil(block_bytes) {
+            return Err(MicroFsError::BadSuperBlock("root entry does not follow the FAT"));
+        }
+        if self.root_blocks == 0 {
+            return Err(MicroFsError::BadSuperBlock("root directory size is zero"));
+        }
+        if self.blocks() < self.data_start() + self.journal_blocks as usize {
+            return Err(MicroFsError::BadSuperBlock("image too small to hold the root directory and the journal"));
+        }
+        Ok(())
+    }
+    
+    // a journal holding the whole FAT and root directory, a save changing more
+    // than it holds grows it over the free blocks before it
+    pub fn default_journal_blocks(&self) -> u32 {
+        let root_sectors = self.root_blocks as usize * self.block_size as usize;
+        let sectors = JOURNAL_SLACK + (self.fat_size as usize).div_ceil(SECTOR_SIZE) + root_sectors;
+        sectors.div_ceil(self.block_size as usize) as u32
+    }
+    
+    // resize the root directory along with the journal covering it
+    pub fn set_root_blocks(&mut self, root_blocks: u32) {
+        self.root_blocks = root_blocks;
+        self.journal_blocks = self.default_journal_blocks();
+    }
+    
+    pub fn blocks(&self) -> usize {
+        self.fat_size as usize / self.fat_width()
+    }
+    
+    pub fn fat_width(&self) -> usize {
+        fat_width(self.version)
+    }
+    
+    pub fn data_start(&self) -> usize {
+        self.root_entry as usize + self.root_blocks as usize
+    }
+    
+    // the journal takes the last blocks of the image
+    pub fn journal_start(&self) -> usize {
+        self.blocks() - self.journal_blocks as usize
+    }
+    
+    // whether the directory entries carry times and attributes
+    pub fn has_attributes(&self) -> bool {
+        self.version & VERSION_ATTRIBUTES != 0
+    }
+    
+    pub fn has_checksums(&self) -> bool {
+        self.version & VERSION_CHECKSUMS != 0
+    }
+    
+    pub fn entry_size(&self) -> usize {
+        if self.has_attributes() || self.has_checksums() {
+            EXTENDED_ENTRY_SIZE
+        } else {
+            ENTRY_SIZE
+        }
+    }
+    
+    pub fn name_size(&self) -> usize {
+        match self.version & 0xff {
+            VERSION_FAT32 => NAME_SIZE - 2,
+            _ => NAME_SIZE
+        }
+    }
+    
+    pub fn has_long_names(&self) -> bool {
+        self.version & VERSION_LONG_NAMES != 0
+    }
+    
+    // bytes of the longest name, the trailing '/' of a directory included
+    pub fn max_name_size(&self) -> usize {
+        if self.has_long_names() {
+            LONG_NAME_SIZE
+        } else {
+            self.name_size()
+        }
+    }
+    
+    // entries taken in a table by a name of `len` bytes
+    pub fn name_slots(&self, len: usize) -> usize {
+        if self.has_long_names() && len > INLINE_NAME_SIZE {
+            1 + (len - INLINE_NAME_SIZE).div_ceil(self.entry_size() - 2)
+        } else {
+            1
         }
     }
+    
+    // bytes of the table holding `entries`
+    pub fn table_size(&self, entries: &[Entry]) -> usize {
+        entries.iter().map(|e| self.name_slots(e.name.len()) * self.entry_size()).sum()
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
-#[repr(C)]
+fn u64_at(raw: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&raw[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn fat_width(version: u16) -> usize {
+    match version & 0xff {
+        VERSION_FAT8 => 1,
+        VERSION_FAT16 => 2,
+        VERSION_FAT32 => 4,
+        _ => 0
+    }
+}
+
+// data of an added file, kept until `save` writes it to the chain starting at `start`
+#[derive(Debug, Clone)]
+pub struct PendingWrite {
+    pub start: u32,
+    pub data: Vec<u8>
+}
+
+// a directory is an entry whose name ends with '/' and whose data blocks hold
+// the records of its children, `size` being the length of that table
+#[derive(Debug, Clone)]
 pub struct Entry {
-    pub name: [u8;26],
-    pub start: u16,
-    pub size: u32
+    // the whole name, which only images with VERSION_LONG_NAMES can store
+    // past the name field
+    pub name: Vec<u8>,
+    pub start: u32,
+    pub size: u32,
+    // only kept by images with VERSION_ATTRIBUTES
+    pub created: u64,
+    pub modified: u64,
+    pub attributes: u8,
+    // CRC32 of the data of a file as of the last save, only kept by images
+    // with VERSION_CHECKSUMS
+    pub checksum: u32,
+    pub children: Vec<Entry>
 }
 impl Entry {
     fn new(name: &str) -> Entry {
-        let mut raw_name : [u8;26] = [0;26];
-        let mut i = 0;
-        for byte in name.bytes() {
-            raw_name[i] = byte;
-            i += 1;
-            if i == 26 { break; }
-        }
+        let now = now();
         Entry {
-            name: raw_name,
+            name: name.as_bytes().to_vec(),
             start: 0,
-            size: 0
+            size: 0,
+            created: now,
+            modified: now,
+            attributes: 0,
+            checksum: 0,
+            children: Vec::new()
+        }
+    }
+    
+    pub fn filename(&self) -> &str {
+        bytes_to_str(&self.name).trim_end_matches('/')
+    }
+    
+    pub fn is_dir(&self) -> bool {
+        bytes_to_str(&self.name).ends_with('/')
+    }
+    
+    pub fn is_read_only(&self) -> bool {
+        self.attributes & ATTR_READ_ONLY != 0
+    }
+    
+    // 32-bit FAT images trade two bytes of the name for a 32-bit start block.
+    // Only the name field is read, the continuation entries of a long name are
+    // appended by `MicroFS::parse_entries`
+    pub fn from_bytes(raw: &[u8], sb: &SuperBlock) -> Entry {
+        let name_size = sb.name_size();
+        let name = raw[..name_size].iter().cloned().take_while(|&byte| byte != 0).collect();
+        let start = if name_size == NAME_SIZE {
+            u16::from_le_bytes([raw[26], raw[27]]) as u32
+        } else {
+            u32::from_le_bytes([raw[24], raw[25], raw[26], raw[27]])
+        };
+        let mut entry = Entry {
+            name,
+            start,
+            size: u32::from_le_bytes([raw[28], raw[29], raw[30], raw[31]]),
+            created: 0,
+            modified: 0,
+            attributes: 0,
+            checksum: 0,
+            children: Vec::new()
+        };
+        if sb.has_attributes() {
+            entry.created = u64_at(raw, 32);
+            entry.modified = u64_at(raw, 40);
+            entry.attributes = raw[48];
+        }
+        if sb.has_checksums() {
+            entry.checksum = u32::from_le_bytes([raw[52], raw[53], raw[54], raw[55]]);
+        }
+        entry
+    }
+    
+    // the entry followed by the continuation entries of its name
+    pub fn to_bytes(&self, sb: &SuperBlock) -> Vec<u8> {
+        let entry_size = sb.entry_size();
+        let mut raw = vec![0; sb.name_slots(self.name.len()) * entry_size];
+        let name_size = sb.name_size();
+        let inline = if sb.has_long_names() { INLINE_NAME_SIZE } else { name_size };
+        let (head, tail) = self.name.split_at(cmp::min(self.name.len(), inline));
+        raw[..head.len()].copy_from_slice(head);
+        for (i, (slot, part)) in raw[entry_size..].chunks_mut(entry_size).zip(tail.chunks(entry_size - 2)).enumerate() {
+            slot[0] = LONG_NAME_MARKER;
+            slot[1] = i as u8 + 1;
+            slot[2..2 + part.len()].copy_from_slice(part);
+        }
+        if name_size == NAME_SIZE {
+            raw[26..28].copy_from_slice(&(self.start as u16).to_le_bytes());
+        } else {
+            raw[24..28].copy_from_slice(&self.start.to_le_bytes());
+        }
+        raw[28..32].copy_from_slice(&self.size.to_le_bytes());
+        if sb.has_attributes() {
+            raw[32..40].copy_from_slice(&self.created.to_le_bytes());
+            raw[40..48].copy_from_slice(&self.modified.to_le_bytes());
+            raw[48] = self.attributes;
+        }
+        if sb.has_checksums() {
+            raw[52..56].copy_from_slice(&self.checksum.to_le_bytes());
         }
+        raw
     }
 }
\ No newline at end of file
@@ -0,0 +1,595 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use fuser::{FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory,
+    ReplyEmpty, ReplyEntry, ReplyOpen, ReplyStatfs, ReplyWrite, Request, TimeOrNow};
+use libc::{c_int, getgid, getuid, EACCES, EBADF, EEXIST, EINVAL, EIO, EISDIR, ENAMETOOLONG, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY,
+    ENOTSUP, O_ACCMODE, O_RDONLY};
+use super::*;
+
+const TTL: Duration = Duration::from_secs(1);
+const ROOT_INO: u64 = 1;
+
+// serve the image at `mountpoint` until it is unmounted, every change is saved
+// when the file it touches is closed or synced and when the image is unmounted
+pub fn mount<D: BlockDevice, P: AsRef<Path>>(fs: MicroFS<D>, mountpoint: P) -> io::Result<()> {
+    let options = [MountOption::FSName("micro_fs".to_string()), MountOption::DefaultPermissions];
+    fuser::mount2(MicroFuse::new(fs), mountpoint, &options)
+}
+
+struct MicroFuse<D: BlockDevice> {
+    fs: MicroFS<D>,
+    // path of every inode handed to the kernel, from the root directory on,
+    // empty once the entry is removed
+    inodes: Vec<String>,
+    // the files opened by the kernel, by file handle
+    handles: HashMap<u64, OpenFile>,
+    next_fh: u64,
+    // data written since the last save
+    dirty: bool
+}
+
+fn errno(err: &MicroFsError) -> c_int {
+    match *err {
+        MicroFsError::NotFound(_) => ENOENT,
+        MicroFsError::NameTooLong(_) => ENAMETOOLONG,
+        MicroFsError::DuplicateName(_) => EEXIST,
+        MicroFsError::NoSpace { .. } | MicroFsError::NotContiguous { .. } | MicroFsError::DirectoryFull | MicroFsError::JournalFull { .. } => ENOSPC,
+        MicroFsError::NotADirectory(_) => ENOTDIR,
+        MicroFsError::IsADirectory(_) => EISDIR,
+        MicroFsError::DirectoryNotEmpty(_) => ENOTEMPTY,
+        MicroFsError::InvalidName(_) | MicroFsError::InvalidMove(_) => EINVAL,
+        MicroFsError::ReadOnly(_) => EACCES,
+        MicroFsError::Unsupported(_) => ENOTSUP,
+        MicroFsError::Io(ref err) => err.raw_os_error().unwrap_or(EIO),
+        _ => EIO
+    }
+}
+
+impl<D: BlockDevice> MicroFuse<D> {
+    fn new(fs: MicroFS<D>) -> MicroFuse<D> {
+        MicroFuse { fs, inodes: vec!["/".to_string()], handles: HashMap::new(), next_fh: 0, dirty: false }
+    }
+
+    fn path(&self, ino: u64) -> Result<String> {
+        match ino.checked_sub(ROOT_INO).and_then(|i| self.inodes.get(i as usize)) {
+            Some(path) if !path.is_empty() => Ok(path.clone()),
+            _ => Err(MicroFsError::NotFound(format!("inode {}", ino)))
+        }
+    }
+
+    fn child(&self, parent: u64, name: &OsStr) -> Result<String> {
+        let parent = self.path(parent)?;
+        let name = match name.to_str() {
+            Some(name) => name,
+            None => return Err(MicroFsError::NotFound(name.to_string_lossy().into_owned()))
+        };
+        Ok(format!("{}/{}", parent.trim_end_matches('/'), name))
+    }
+
+    fn ino(&mut self, path: &str) -> u64 {
+        match self.inodes.iter().position(|p| p == path) {
+            Some(i) => i as u64 + ROOT_INO,
+            None => {
+                self.inodes.push(path.to_string());
+                self.inodes.len() as u64 - 1 + ROOT_INO
+            }
+        }
+    }
+
+    fn attr(&mut self, path: &str) -> Result<FileAttr> {
+        let block_sectors = self.fs.sb.block_size as u64;
+        let (kind, size, blocks, perm, nlink, created, modified) = if split_path(path).is_empty() {
+            let size = self.fs.entries_size() as u64;
+            (FileType::Directory, size, size / (SECTOR_SIZE as u64 * block_sectors), 0o755, 2, 0, 0)
+        } else {
+            let entry = self.fs.find(path)?.clone();
+            let blocks = self.fs.get_blocks(&entry)?.len() as u64;
+            let (kind, perm, nlink) = if entry.is_dir() {
+                (FileType::Directory, 0o755, 2)
+            } else if entry.attributes & ATTR_EXECUTABLE != 0 {
+                (FileType::RegularFile, 0o755, 1)
+            } else {
+                (FileType::RegularFile, 0o644, 1)
+            };
+            let perm = if entry.is_read_only() { perm & !0o222 } else { perm };
+            (kind, entry.size as u64, blocks, perm, nlink, entry.created, entry.modified)
+        };
+        // the image keeps no owner, and no times before VERSION_ATTRIBUTES
+        let (uid, gid) = unsafe { (getuid(), getgid()) };
+        let (created, modified) = if self.fs.sb.has_attributes() {
+            (UNIX_EPOCH + Duration::from_secs(created), UNIX_EPOCH + Duration::from_secs(modified))
+        } else {
+            (UNIX_EPOCH, UNIX_EPOCH)
+        };
+        Ok(FileAttr {
+            ino: self.ino(path),
+            size,
+            blocks: blocks * block_sectors,
+            atime: modified,
+            mtime: modified,
+            ctime: modified,
+            crtime: created,
+            kind,
+            perm,
+            nlink,
+            uid,
+            gid,
+            rdev: 0,
+            blksize: (SECTOR_SIZE as u32) * self.fs.sb.block_size as u32,
+            flags: 0
+        })
+    }
+
+    // forget the inode of a removed entry, a new entry at its path gets another one
+    fn forget(&mut self, path: &str) {
+        for p in &mut self.inodes {
+            if p == path {
+                p.clear();
+            }
+        }
+        self.handles.retain(|_, file| file.path != path);
+    }
+
+    fn open_handle(&mut self, ino: u64, flags: i32) -> Result<u64> {
+        let path = self.path(ino)?;
+        let mode = if flags & O_ACCMODE == O_RDONLY { OpenMode::Read } else { OpenMode::Write };
+        let file = self.fs.open_file(&path, mode)?.detach()?;
+        self.next_fh += 1;
+        self.handles.insert(self.next_fh, file);
+        Ok(self.next_fh)
+    }
+
+    // run `f` on the handle opened for `fh`, it stays open until released
+    fn with_handle<T, F: FnOnce(&mut FileHandle<'_, D>) -> Result<T>>(&mut self, fh: u64, f: F) -> Result<T> {
+        let file = match self.handles.remove(&fh) {
+            Some(file) => file,
+            None => return Err(MicroFsError::Io(io::Error::from_raw_os_error(EBADF)))
+        };
+        let mut handle = self.fs.attach(file)?;
+        let res = f(&mut handle);
+        self.handles.insert(fh, handle.detach()?);
+        res
+    }
+
+    fn close_handle(&mut self, fh: u64) -> Result<()> {
+        if let Some(file) = self.handles.remove(&fh) {
+            // dropping the handle flushes it
+            self.fs.attach(file)?;
+        }
+        if self.dirty { self.save() } else { Ok(()) }
+    }
+
+    fn save(&mut self) -> Result<()> {
+        // the files still open bring their time and checksum up to date first
+        let open: Vec<u64> = self.handles.keys().cloned().collect();
+        for fh in open {
+            self.with_handle(fh, |file| Ok(file.flush()?))?;
+        }
+        self.fs.save()?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    // the mode only maps to the read-only and executable attributes, and like
+    // the times it is ignored by images without them
+    fn set_attr(&mut self, ino: u64, mode: Option<u32>, size: Option<u64>, mtime: Option<TimeOrNow>) -> Result<FileAttr> {
+        let path = self.path(ino)?;
+        if let Some(size) = size {
+            self.fs.open_file(&path, OpenMode::Write)?.set_len(size)?;
+            self.save()?;
+        }
+        if self.fs.sb.has_attributes() && !split_path(&path).is_empty() && (mode.is_some() || mtime.is_some()) {
+            if let Some(mode) = mode {
+                let mut attributes = self.fs.find(&path)?.attributes & !(ATTR_READ_ONLY | ATTR_EXECUTABLE);
+                if mode & 0o200 == 0 {
+                    attributes |= ATTR_READ_ONLY;
+                }
+                if mode & 0o100 != 0 && !self.fs.find(&path)?.is_dir() {
+                    attributes |= ATTR_EXECUTABLE;
+                }
+                self.fs.set_attributes(&path, attributes)?;
+            }
+            match mtime {
+                Some(TimeOrNow::SpecificTime(time)) => self.fs.touch(&path, Some(unix_time(time)))?,
+                Some(TimeOrNow::Now) => self.fs.touch(&path, None)?,
+                None => ()
+            }
+            self.save()?;
+        }
+        self.attr(&path)
+    }
+
+    fn read_at(&mut self, fh: u64, offset: u64, size: u32) -> Result<Vec<u8>> {
+        self.with_handle(fh, |file| {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut data = Vec::new();
+            file.take(size as u64).read_to_end(&mut data)?;
+            Ok(data)
+        })
+    }
+
+    // the data reaches the image right away, the entry is only saved once the
+    // file is released or synced
+    fn write_at(&mut self, fh: u64, offset: u64, data: &[u8]) -> Result<()> {
+        self.with_handle(fh, |file| {
+            file.seek(SeekFrom::Start(offset))?;
+            Ok(file.write_all(data)?)
+        })?;
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn create_file(&mut self, parent: u64, name: &OsStr, flags: i32) -> Result<(FileAttr, u64)> {
+        let path = self.child(parent, name)?;
+        if self.fs.find(&path).is_ok() {
+            return Err(MicroFsError::DuplicateName(path));
+        }
+        self.fs.open_file(&path, OpenMode::Create)?;
+        self.save()?;
+        let attr = self.attr(&path)?;
+        Ok((attr, self.open_handle(attr.ino, flags)?))
+    }
+
+    fn make_dir(&mut self, parent: u64, name: &OsStr) -> Result<FileAttr> {
+        let path = self.child(parent, name)?;
+        self.fs.mkdir(&path)?;
+        self.save()?;
+        self.attr(&path)
+    }
+
+    fn remove(&mut self, parent: u64, name: &OsStr, dir: bool) -> Result<()> {
+        let path = self.child(parent, name)?;
+        if dir {
+            self.fs.rmdir(&path, false)?;
+        } else {
+            self.fs.del(&path)?;
+        }
+        self.forget(&path);
+        self.save()
+    }
+
+    // like rename(2), a file moved onto another replaces it in one step: the
+    // entry of the target keeps its place and takes the data of the moved file
+    // before its own data is freed, so a failure leaves both files whole
+    fn overwrite(&mut self, old: &str, new: &str) -> Result<()> {
+        let target = self.fs.find(new)?.clone();
+        if target.is_read_only() {
+            return Err(MicroFsError::ReadOnly(new.to_string()));
+        }
+        let mut moved = self.fs.find(old)?.clone();
+        moved.name = target.name.clone();
+        *self.fs.find_mut(new)? = moved;
+        self.fs.remove_entries(old, false)?;
+        self.forget(new);
+        self.fs.free_entry(&target)
+    }
+
+    fn move_entry(&mut self, parent: u64, name: &OsStr, new_parent: u64, new_name: &OsStr) -> Result<()> {
+        let old = self.child(parent, name)?;
+        let new = self.child(new_parent, new_name)?;
+        if old == new {
+            return Ok(());
+        }
+        let replaced = match self.fs.find(&new) {
+            Ok(entry) => Some(entry.is_dir()),
+            Err(_) => None
+        };
+        match replaced {
+            Some(false) if !self.fs.find(&old)?.is_dir() => self.overwrite(&old, &new)?,
+            Some(_) => return Err(MicroFsError::DuplicateName(new)),
+            None => self.fs.rename(&old, &new)?
+        }
+        // the inodes and open files below the moved entry follow it
+        let below = format!("{}/", old);
+        let paths = self.inodes.iter_mut().chain(self.handles.values_mut().map(|file| &mut file.path));
+        for path in paths {
+            if *path == old || path.starts_with(&below) {
+                *path = format!("{}{}", new, &path[old.len()..]);
+            }
+        }
+        self.save()
+    }
+
+    fn children(&mut self, ino: u64) -> Result<Vec<(u64, FileType, String)>> {
+        let path = self.path(ino)?;
+        let parent = split_parent(&path).0;
+        let mut children = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (self.ino(&format!("/{}", parent)), FileType::Directory, "..".to_string())
+        ];
+        for entry in self.fs.list(&path)? {
+            let kind = if entry.is_dir { FileType::Directory } else { FileType::RegularFile };
+            children.push((self.ino(&entry.path), kind, entry.name));
+        }
+        Ok(children)
+    }
+}
+
+impl<D: BlockDevice> Filesystem for MicroFuse<D> {
+    fn destroy(&mut self) {
+        let _ = self.save();
+    }
+
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        match self.child(parent, name).and_then(|path| self.attr(&path)) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        match self.path(ino).and_then(|path| self.attr(&path)) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+
+    fn setattr(&mut self, _req: &Request, ino: u64, mode: Option<u32>, _uid: Option<u32>, _gid: Option<u32>,
+               size: Option<u64>, _atime: Option<TimeOrNow>, mtime: Option<TimeOrNow>,
+               _ctime: Option<SystemTime>, _fh: Option<u64>, _crtime: Option<SystemTime>,
+               _chgtime: Option<SystemTime>, _bkuptime: Option<SystemTime>,
+               _flags: Option<u32>, reply: ReplyAttr) {
+        match self.set_attr(ino, mode, size, mtime) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+
+    fn mkdir(&mut self, _req: &Request, parent: u64, name: &OsStr, _mode: u32, _umask: u32, reply: ReplyEntry) {
+        match self.make_dir(parent, name) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+
+    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        match self.remove(parent, name, false) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+
+    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        match self.remove(parent, name, true) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+
+    fn rename(&mut self, _req: &Request, parent: u64, name: &OsStr, newparent: u64, newname: &OsStr, _flags: u32,
+              reply: ReplyEmpty) {
+        match self.move_entry(parent, name, newparent, newname) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+
+    fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+        match self.open_handle(ino, flags) {
+            Ok(fh) => reply.opened(fh, 0),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+
+    fn read(&mut self, _req: &Request, _ino: u64, fh: u64, offset: i64, size: u32, _flags: i32,
+            _lock_owner: Option<u64>, reply: ReplyData) {
+        match self.read_at(fh, offset as u64, size) {
+            Ok(data) => reply.data(&data),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+
+    fn write(&mut self, _req: &Request, _ino: u64, fh: u64, offset: i64, data: &[u8], _write_flags: u32,
+             _flags: i32, _lock_owner: Option<u64>, reply: ReplyWrite) {
+        match self.write_at(fh, offset as u64, data) {
+            Ok(()) => reply.written(data.len() as u32),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+
+    fn flush(&mut self, _req: &Request, _ino: u64, _fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        reply.ok();
+    }
+
+    fn release(&mut self, _req: &Request, _ino: u64, fh: u64, _flags: i32, _lock_owner: Option<u64>,
+               _flush: bool, reply: ReplyEmpty) {
+        match self.close_handle(fh) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+
+    fn fsync(&mut self, _req: &Request, _ino: u64, _fh: u64, _datasync: bool, reply: ReplyEmpty) {
+        match self.save() {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        let children = match self.children(ino) {
+            Ok(children) => children,
+            Err(e) => return reply.error(errno(&e))
+        };
+        for (i, (child, kind, name)) in children.into_iter().enumerate().skip(offset as usize) {
+            // the offset given back to the kernel is the one of the next entry
+            if reply.add(child, i as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn statfs(&mut self, _req: &Request, _ino: u64, reply: ReplyStatfs) {
+        let free = self.fs.free_blocks() as u64;
+        let block_bytes = (SECTOR_SIZE as u32) * self.fs.sb.block_size as u32;
+        reply.statfs(self.fs.data_blocks() as u64, free, free, 0, 0, block_bytes, self.fs.sb.max_name_size() as u32, block_bytes);
+    }
+
+    fn create(&mut self, _req: &Request, parent: u64, name: &OsStr, _mode: u32, _umask: u32, flags: i32,
+              reply: ReplyCreate) {
+        match self.create_file(parent, name, flags) {
+            Ok((attr, fh)) => reply.created(&TTL, &attr, 0, fh, 0),
+            Err(e) => reply.error(errno(&e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // counts the flushes reaching the device
+    struct CountingDevice {
+        inner: MemDevice,
+        flushes: usize
+    }
+
+    impl BlockDevice for CountingDevice {
+        fn read_sector(&mut self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()> {
+            self.inner.read_sector(sector, buf)
+        }
+
+        fn write_sector(&mut self, sector: u64, buf: &[u8; SECTOR_SIZE]) -> io::Result<()> {
+            self.inner.write_sector(sector, buf)
+        }
+
+        fn sector_count(&self) -> u64 {
+            self.inner.sector_count()
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            self.inner.flush()
+        }
+    }
+
+    fn mounted(files: &[(&str, &[u8])]) -> MicroFuse<CountingDevice> {
+        let mut sb = SuperBlock::new("test", 1, 200 * 512);
+        sb.version |= VERSION_ATTRIBUTES | VERSION_CHECKSUMS;
+        let dev = CountingDevice { inner: MemDevice::new(200), flushes: 0 };
+        let mut fs = MicroFS::format_device(dev, sb).unwrap();
+        for &(path, data) in files {
+            fs.add_data(path, data.to_vec(), AllocPolicy::FirstFit).unwrap();
+        }
+        fs.save().unwrap();
+        MicroFuse::new(fs)
+    }
+
+    fn read_all(fuse: &mut MicroFuse<CountingDevice>, path: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        fuse.fs.open_file(path, OpenMode::Read).unwrap().read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn inodes() {
+        let mut fuse = mounted(&[]);
+        fuse.fs.mkdir("/d").unwrap();
+        fuse.fs.mkdir("/d/e").unwrap();
+        assert_eq!(fuse.path(ROOT_INO).unwrap(), "/");
+        let d = fuse.attr("/d").unwrap().ino;
+        assert_eq!(d, ROOT_INO + 1);
+        assert_eq!(fuse.child(d, OsStr::new("e")).unwrap(), "/d/e");
+        let e = fuse.attr("/d/e").unwrap().ino;
+        assert_eq!(fuse.attr("/d/e").unwrap().ino, e);
+        assert_eq!(fuse.path(e).unwrap(), "/d/e");
+        match fuse.path(e + 1) {
+            Err(MicroFsError::NotFound(_)) => (),
+            res => panic!("unexpected result {:?}", res)
+        }
+        let names: Vec<String> = fuse.children(d).unwrap().into_iter().map(|(_, _, name)| name).collect();
+        assert_eq!(names, [".", "..", "e"]);
+
+        // a removed entry forgets its inode, a new one at its path gets another
+        fuse.remove(d, OsStr::new("e"), true).unwrap();
+        match fuse.path(e) {
+            Err(MicroFsError::NotFound(_)) => (),
+            res => panic!("unexpected result {:?}", res)
+        }
+        let (attr, _) = fuse.create_file(d, OsStr::new("e"), O_RDONLY).unwrap();
+        assert!(attr.ino > e);
+    }
+
+    #[test]
+    fn move_entries() {
+        let mut fuse = mounted(&[("/a", b"one"), ("/b", b"two")]);
+        fuse.fs.mkdir("/d").unwrap();
+        fuse.fs.mkdir("/d/e").unwrap();
+        let d = fuse.attr("/d").unwrap().ino;
+        let e = fuse.attr("/d/e").unwrap().ino;
+        let a = fuse.attr("/a").unwrap().ino;
+        let b = fuse.attr("/b").unwrap().ino;
+
+        // the inodes below a moved directory follow it
+        fuse.move_entry(ROOT_INO, OsStr::new("d"), ROOT_INO, OsStr::new("f")).unwrap();
+        assert_eq!(fuse.path(d).unwrap(), "/f");
+        assert_eq!(fuse.path(e).unwrap(), "/f/e");
+
+        // a file moved onto another takes its place, open or not
+        let fh = fuse.open_handle(a, O_RDONLY).unwrap();
+        let free = fuse.fs.free_blocks();
+        fuse.move_entry(ROOT_INO, OsStr::new("a"), ROOT_INO, OsStr::new("b")).unwrap();
+        assert_eq!(fuse.path(a).unwrap(), "/b");
+        match fuse.path(b) {
+            Err(MicroFsError::NotFound(_)) => (),
+            res => panic!("unexpected result {:?}", res)
+        }
+        assert_eq!(fuse.read_at(fh, 0, 10).unwrap(), b"one");
+        assert_eq!(read_all(&mut fuse, "/b"), b"one");
+        assert!(fuse.fs.find("/a").is_err());
+        assert_eq!(fuse.fs.free_blocks(), free + 1);
+        assert!(fuse.fs.check().unwrap().is_clean());
+
+        // a move that cannot be done leaves both entries as they were
+        fuse.fs.add_data("/c", b"three".to_vec(), AllocPolicy::FirstFit).unwrap();
+        fuse.fs.set_attributes("/c", ATTR_READ_ONLY).unwrap();
+        fuse.save().unwrap();
+        match fuse.move_entry(ROOT_INO, OsStr::new("b"), ROOT_INO, OsStr::new("c")) {
+            Err(MicroFsError::ReadOnly(_)) => (),
+            res => panic!("unexpected result {:?}", res)
+        }
+        match fuse.move_entry(ROOT_INO, OsStr::new("b"), ROOT_INO, OsStr::new("f")) {
+            Err(MicroFsError::DuplicateName(_)) => (),
+            res => panic!("unexpected result {:?}", res)
+        }
+        assert_eq!(read_all(&mut fuse, "/b"), b"one");
+        assert_eq!(read_all(&mut fuse, "/c"), b"three");
+        assert_eq!(fuse.path(a).unwrap(), "/b");
+    }
+
+    #[test]
+    fn open_files() {
+        let mut fuse = mounted(&[("/a", b"")]);
+        let a = fuse.attr("/a").unwrap().ino;
+        let fh = fuse.open_handle(a, libc::O_RDWR).unwrap();
+        let flushes = fuse.fs.dev.flushes;
+        for i in 0..100 {
+            fuse.write_at(fh, i * 100, &[i as u8; 100]).unwrap();
+        }
+        // the writes are neither flushed nor saved until the file is released
+        assert_eq!(fuse.fs.dev.flushes, flushes);
+        assert_eq!(fuse.attr("/a").unwrap().size, 10000);
+        assert_eq!(fuse.read_at(fh, 9950, 100).unwrap(), [99; 50]);
+        fuse.close_handle(fh).unwrap();
+        assert!(fuse.fs.dev.flushes > flushes);
+        match fuse.read_at(fh, 0, 1) {
+            Err(MicroFsError::Io(ref err)) if err.raw_os_error() == Some(EBADF) => (),
+            res => panic!("unexpected result {:?}", res)
+        }
+
+        let dev = MemDevice::from_vec(fuse.fs.dev.inner.into_inner());
+        let mut test_fs = MicroFS::open_device(dev).unwrap();
+        assert!(test_fs.check().unwrap().is_clean());
+        assert!(test_fs.verify().unwrap().is_clean());
+        let mut data = Vec::new();
+        test_fs.open_file("/a", OpenMode::Read).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data.len(), 10000);
+        assert_eq!(&data[4200..4300], &[42; 100][..]);
+    }
+}
@@ -0,0 +1,49 @@
+use super::*;
+
+impl<D: BlockDevice> MicroFS<D> {
+    // only the directory entry changes: a rename in place rewrites the name, a
+    // move detaches the entry from its parent and attaches it to `new`, the
+    // chain and data blocks of the entry are left untouched
+    pub fn rename(&mut self, old: &str, new: &str) -> Result<()> {
+        let entry = self.find(old)?.clone();
+        let (old_dir, old_name) = split_parent(old);
+        // moving into an existing directory keeps the name
+        let (dir, name) = match self.dir_entries(new) {
+            Ok(_) => (split_path(new).join("/"), old_name.to_string()),
+            Err(_) => {
+                let (dir, name) = split_parent(new);
+                (dir, name.to_string())
+            }
+        };
+        let mut parts = split_path(&dir);
+        parts.push(&name);
+        let target = format!("/{}", parts.join("/"));
+        check_name(&target, &name)?;
+        if self.dir_entries(&dir)?.iter().any(|e| e.filename() == name) {
+            return Err(MicroFsError::DuplicateName(target));
+        }
+        let suffix = if entry.is_dir() { 1 } else { 0 };
+        if name.len() + suffix > self.sb.max_name_size() {
+            return Err(MicroFsError::NameTooLong(name));
+        }
+        if entry.is_dir() && split_path(&target).starts_with(&split_path(old)) {
+            return Err(MicroFsError::InvalidMove(target));
+        }
+        let new_name = if entry.is_dir() { format!("{}/", name) } else { name };
+        
+        if split_path(&dir) == split_path(&old_dir) {
+            // a longer name may take more entries of the table
+            let old_name = mem::replace(&mut self.find_mut(old)?.name, new_name.into_bytes());
+            if let Err(e) = self.resize_dir(&dir) {
+                self.find_mut(&target)?.name = old_name;
+                return Err(e);
+            }
+            return Ok(());
+        }
+        let mut moved = entry.clone();
+        moved.name = new_name.into_bytes();
+        self.insert_entry(&dir, moved)?;
+        self.remove_entries(old, entry.is_dir())?;
+        Ok(())
+    }
+}
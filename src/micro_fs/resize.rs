@@ -0,0 +1,124 @@
+use super::*;
+
+fn check_names(entries: &[Entry], parent: &str, name_size: usize) -> Result<()> {
+    for entry in entries {
+        let path = format!("{}/{}", parent, entry.filename());
+        if entry.name.len() > name_size {
+            return Err(MicroFsError::NameTooLong(path));
+        }
+        check_names(&entry.children, &path, name_size)?;
+    }
+    Ok(())
+}
+
+impl<D: BlockDevice> MicroFS<D> {
+    // grow or shrink the image to `size` bytes. The FAT is widened when the new
+    // blocks cannot be addressed otherwise, the blocks in the way of a larger
+    // FAT or past the end of a smaller image are moved to free blocks first.
+    // Moved blocks only go to blocks the image does not use and the new super
+    // block, FAT and directories go through the journal, so an interrupted
+    // resize leaves either the old or the new image, unless the new FAT is too
+    // large for the journal or reaches it, in which case they are written in place
+    pub fn resize(&mut self, size: usize) -> Result<()> {
+        self.save()?;
+        let old_sb = self.sb;
+        // the FAT never gets narrower, the layout of the entries is kept
+        let fat_version = cmp::max(old_sb.version & 0xff, SuperBlock::new("", old_sb.block_size, size).version);
+        let version = fat_version | (old_sb.version & !0xff);
+        let mut sb = SuperBlock::with_version("", old_sb.block_size, size, version);
+        sb.label = old_sb.label;
+        sb.set_root_blocks(old_sb.root_blocks);
+        if old_sb.journal_blocks == 0 {
+            sb.journal_blocks = 0;
+        } else {
+            sb.journal_blocks = cmp::max(sb.journal_blocks, old_sb.journal_blocks);
+        }
+        sb.validate()?;
+        check_names(&self.entries, "", sb.max_name_size())?;
+
+        let sectors = (sb.blocks() * sb.block_size as usize) as u64;
+        if self.dev.sector_count() < sectors {
+            self.dev.set_sector_count(sectors)?;
+        }
+        let (fat, entries) = (self.fat.clone(), self.entries.clone());
+        if let Err(e) = self.relocate(sb) {
+            self.sb = old_sb;
+            self.fat = fat;
+            self.entries = entries;
+            return Err(e);
+        }
+        self.disk_fat = self.fat.clone();
+        if sb.blocks() < old_sb.blocks() {
+            self.dev.set_sector_count(sectors)?;
+        }
+        Ok(())
+    }
+
+    fn relocate(&mut self, sb: SuperBlock) -> Result<()> {
+        let old_sb = self.sb;
+        let block_bytes = SECTOR_SIZE * sb.block_size as usize;
+        if sb.blocks() > old_sb.blocks() {
+            let mut fat = Fat::new(sb.fat_width(), sb.blocks());
+            for block in 0..old_sb.blocks() {
+                fat.set(block, self.fat.get(block));
+            }
+            self.fat = fat;
+        }
+        let data_start = sb.data_start();
+        let data_end = cmp::min(sb.journal_start(), self.fat.max_block() + 1);
+
+        // blocks allocated to no entry are dropped rather than moved
+        let (mut chains, mut owners) = self.chains()?;
+        let mut moved = Vec::new();
+        for block in (old_sb.data_start()..old_sb.blocks()).filter(|&block| block < data_start || block >= data_end) {
+            if owners[block].is_some() {
+                moved.push(block);
+            } else {
+                self.fat.set(block, FatEntry::Free);
+            }
+        }
+        // the blocks they go to must not be used by the old image, in case it stops
+        // before the new one is written
+        let free: Vec<usize> = (data_start..data_end).filter(|&block| {
+            self.fat.get(block) == FatEntry::Free && block >= old_sb.data_start()
+                && (block < old_sb.journal_start() || block >= old_sb.blocks())
+                && (block >= self.disk_fat.len() || self.disk_fat.get(block) == FatEntry::Free)
+        }).collect();
+        if free.len() < moved.len() {
+            return Err(MicroFsError::NoSpace { needed: moved.len(), available: free.len() });
+        }
+        for (&block, &dest) in moved.iter().zip(&free) {
+            let owner = owners[block].unwrap();
+            self.move_block(&mut chains, &mut owners, owner, dest)?;
+        }
+        if sb.blocks() < old_sb.blocks() {
+            let mut fat = Fat::new(sb.fat_width(), sb.blocks());
+            for block in 0..sb.blocks() {
+                fat.set(block, self.fat.get(block));
+            }
+            self.fat = fat;
+        }
+
+        self.sb = sb;
+        let mut writes = self.metadata_writes()?;
+        writes.push((0, sb.to_bytes().to_vec()));
+        // the old journal is cleared once applied, a new one elsewhere starts empty
+        let old_journal = old_sb.journal_start()..old_sb.blocks();
+        if sb.journal_blocks != 0 && (old_sb.journal_blocks == 0 || !old_journal.contains(&sb.journal_start())) {
+            writes.push(((sb.journal_start() * block_bytes) as u64, vec![0; SECTOR_SIZE]));
+        }
+        self.sb = old_sb;
+        if data_start > old_sb.journal_start() {
+            self.sb.journal_blocks = 0;
+        }
+        let res = match self.commit(&[], &writes) {
+            Err(MicroFsError::JournalFull { .. }) => {
+                self.sb.journal_blocks = 0;
+                self.commit(&[], &writes)
+            },
+            res => res
+        };
+        self.sb = sb;
+        res
+    }
+}
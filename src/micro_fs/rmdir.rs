@@ -0,0 +1,19 @@
+use super::*;
+
+impl<D: BlockDevice> MicroFS<D> {
+    pub fn rmdir(&mut self, path: &str, recursive: bool) -> Result<()> {
+        {
+            let entry = self.find(path)?;
+            if !entry.is_dir() {
+                return Err(MicroFsError::NotADirectory(path.to_string()));
+            }
+            if !entry.children.is_empty() && !recursive {
+                return Err(MicroFsError::DirectoryNotEmpty(path.to_string()));
+            }
+        }
+        for entry in self.remove_entries(path, true)? {
+            self.free_entry(&entry)?;
+        }
+        Ok(())
+    }
+}
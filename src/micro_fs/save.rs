@@ -1,54 +1,69 @@
-use std::path::Path;
 use super::*;
 
-impl MicroFS {
-    pub fn save(&mut self) {
-        unsafe {
-            let mut image = OpenOptions::new().read(true).write(true).open(self.image.clone()).expect("File not found !");
-            image.seek(SeekFrom::Start(SECTOR_SIZE as u64)).expect("File seek failed !");
-            image.write_all(&(self.fat)).expect("Failed to write in file!");
-            
-            image.seek(SeekFrom::Start(self.root_entry() as u64)).expect("File seek failed !");
-            for _i in 0..(self.entries_size() / SECTOR_SIZE) {
-                image.write_all(&[0;SECTOR_SIZE]).expect("Failed to write in file!");
+impl<D: BlockDevice> MicroFS<D> {
+    pub fn save(&mut self) -> Result<()> {
+        if self.sb.table_size(&self.entries) > self.entries_size() {
+            return Err(MicroFsError::DirectoryFull);
+        }
+        let block_bytes = SECTOR_SIZE * self.sb.block_size as usize;
+        let mut data = Vec::new();
+        if self.sb.has_checksums() {
+            for pending in &self.pending {
+                if let Some(entry) = file_at(&mut self.entries, pending.start) {
+                    entry.checksum = crc32(0, &pending.data);
+                }
             }
-            
-            image.seek(SeekFrom::Start(self.root_entry() as u64)).expect("File seek failed !");
-            for entry in self.entries.clone() {
-                image.write_all(&(entry.name)).expect("Failed to write in file!");
-                image.write_all(&(mem::transmute::<u16, [u8; 2]>(entry.start))).expect("Failed to write in file!");
-                image.write_all(&(mem::transmute::<u32, [u8; 4]>(entry.size))).expect("Failed to write in file!");
+        }
+        for pending in &self.pending {
+            let blocks = self.get_blocks(&Entry { start: pending.start, ..Entry::new("") })?;
+            for (block, chunk) in blocks.iter().zip(pending.data.chunks(block_bytes)) {
+                data.push(((block * block_bytes) as u64, chunk.to_vec()));
             }
-            for entry in self.new_entries.clone() {
-                // get filename from path
-                let path = bytes_to_str(&entry.name);
-                let filename = match Path::new(path).file_name() {
-                    Some(name) => name,
-                    None => return,
-                };
-                let filename = match filename.to_str() {
-                    Some(name) => name,
-                    None => return,
-                };
-                let mut raw_name : [u8;26] = [0;26];
-                let mut i = 0;
-                for byte in filename.bytes() {
-                    raw_name[i] = byte;
-                    i += 1;
-                    if i == 26 { break; }
-                }
-                
-                image.write_all(&(raw_name)).expect("Failed to write in file!");
-                image.write_all(&(mem::transmute::<u16, [u8; 2]>(entry.start))).expect("Failed to write in file!");
-                image.write_all(&(mem::transmute::<u32, [u8; 4]>(entry.size))).expect("Failed to write in file!");
-                
-                let mut file_buffer = Vec::new();
-                let s = bytes_to_str(&(entry.name));
-                let mut file = File::open(s).expect("File not found !");
-                file.read_to_end(&mut file_buffer).expect("Something went wrong reading the file !");
-                let mut blocks = self.get_blocks(&mut entry.clone());
-                self.write_data(&mut blocks, file_buffer);
+        }
+        let writes = self.metadata_writes()?;
+        
+        // the data stays pending until the save is committed
+        self.commit(&data, &writes)?;
+        self.pending.clear();
+        self.disk_fat = self.fat.clone();
+        Ok(())
+    }
+    
+    // the FAT and every directory table as they are in memory
+    pub(crate) fn metadata_writes(&self) -> Result<Vec<(u64, Vec<u8>)>> {
+        let mut writes = vec![(SECTOR_SIZE as u64, self.fat.as_bytes().to_vec())];
+        self.dir_writes(&self.entries, &mut writes)?;
+        let mut raw_entries = self.dir_table(&self.entries);
+        raw_entries.resize(self.entries_size(), 0);
+        writes.push((self.root_entry() as u64, raw_entries));
+        Ok(writes)
+    }
+    
+    fn dir_writes(&self, entries: &[Entry], writes: &mut Vec<(u64, Vec<u8>)>) -> Result<()> {
+        let block_bytes = SECTOR_SIZE * self.sb.block_size as usize;
+        for entry in entries.iter().filter(|e| e.is_dir()) {
+            let blocks = self.get_blocks(entry)?;
+            let mut raw_entries = self.dir_table(&entry.children);
+            raw_entries.resize(blocks.len() * block_bytes, 0);
+            for (block, chunk) in blocks.iter().zip(raw_entries.chunks(block_bytes)) {
+                writes.push(((block * block_bytes) as u64, chunk.to_vec()));
+            }
+            self.dir_writes(&entry.children, writes)?;
+        }
+        Ok(())
+    }
+}
+
+// the file whose chain starts at `start`
+fn file_at(entries: &mut [Entry], start: u32) -> Option<&mut Entry> {
+    for entry in entries {
+        if entry.is_dir() {
+            if let Some(file) = file_at(&mut entry.children, start) {
+                return Some(file);
             }
+        } else if entry.start == start {
+            return Some(entry);
         }
     }
-}
\ No newline at end of file
+    None
+}
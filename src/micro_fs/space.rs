@@ -0,0 +1,88 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use super::*;
+
+// what `check_fit` reports of a set of host files to add to a directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FitReport {
+    pub files: usize,
+    // entries taken by the files in the directory table, more than one for a
+    // name continued in other entries
+    pub needed_entries: usize,
+    // blocks taken by the files, and by the directory table growing to hold them
+    pub needed_blocks: usize,
+    pub free_blocks: usize,
+    // entries the directory can still take, None for a directory growing as needed
+    pub free_entries: Option<usize>
+}
+
+impl FitReport {
+    pub fn fits(&self) -> bool {
+        self.needed_blocks <= self.free_blocks && self.free_entries.is_none_or(|free| self.needed_entries <= free)
+    }
+}
+
+impl<D: BlockDevice> MicroFS<D> {
+    // blocks of the data area, whether used or free
+    pub fn data_blocks(&self) -> usize {
+        self.data_end().saturating_sub(self.sb.data_start())
+    }
+
+    pub fn free_blocks(&self) -> usize {
+        (self.sb.data_start()..self.data_end()).filter(|&block| self.fat.get(block) == FatEntry::Free).count()
+    }
+
+    pub fn used_blocks(&self) -> usize {
+        self.data_blocks() - self.free_blocks()
+    }
+
+    pub fn free_bytes(&self) -> usize {
+        self.free_blocks() * SECTOR_SIZE * self.sb.block_size as usize
+    }
+
+    // bytes allocated to files past their end, in their last block
+    pub fn slack_bytes(&self) -> usize {
+        fn slack(entries: &[Entry], block_bytes: usize) -> usize {
+            entries.iter().map(|entry| {
+                if entry.is_dir() {
+                    slack(&entry.children, block_bytes)
+                } else {
+                    block_bytes - entry.size as usize % block_bytes
+                }
+            }).sum()
+        }
+        slack(&self.entries, SECTOR_SIZE * self.sb.block_size as usize)
+    }
+
+    // whether the host files `paths` could be added to the directory `dest`,
+    // counted as new files, without touching the image
+    pub fn check_fit(&self, paths: &[&str], dest: &str) -> Result<FitReport> {
+        let block_bytes = SECTOR_SIZE * self.sb.block_size as usize;
+        let mut needed_blocks = 0;
+        let mut needed_entries = 0;
+        for path in paths {
+            let metadata = match fs::metadata(path) {
+                Ok(metadata) => metadata,
+                Err(ref e) if e.kind() == ErrorKind::NotFound => return Err(MicroFsError::NotFound(path.to_string())),
+                Err(e) => return Err(MicroFsError::Io(e))
+            };
+            if metadata.is_dir() {
+                return Err(MicroFsError::IsADirectory(path.to_string()));
+            }
+            needed_blocks += metadata.len() as usize / block_bytes + 1;
+            let name = Path::new(path).file_name().map_or(0, |name| name.len());
+            needed_entries += self.sb.name_slots(name);
+        }
+        let table_size = self.sb.table_size(self.dir_entries(dest)?);
+        let entry_size = self.sb.entry_size();
+        let free_entries = if split_path(dest).is_empty() {
+            Some(self.entries_size().saturating_sub(table_size) / entry_size)
+        } else {
+            // the table of a sub-directory takes more blocks as it grows
+            needed_blocks += (table_size + needed_entries * entry_size) / block_bytes - table_size / block_bytes;
+            None
+        };
+        Ok(FitReport { files: paths.len(), needed_entries, needed_blocks, free_blocks: self.free_blocks(), free_entries })
+    }
+}
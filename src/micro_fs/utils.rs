@@ -1,118 +1,360 @@
+use std::time::{SystemTime, UNIX_EPOCH};
 use super::*;
 
-impl MicroFS {
-    pub fn fat_size(&mut self) -> usize {
+impl<D: BlockDevice> MicroFS<D> {
+    pub fn fat_size(&self) -> usize {
         self.sb.fat_size as usize
     }
     
-    pub fn root_entry(&mut self) -> usize {
+    pub fn root_entry(&self) -> usize {
         self.sb.root_entry as usize * self.sb.block_size as usize * SECTOR_SIZE
     }
     
-    pub fn entries_size(&mut self) -> usize {
-        self.sb.block_size as usize * SECTOR_SIZE
-    }
-    
-    pub fn set_fat(&mut self) {
-        let mut file = File::open(self.image.clone()).expect("File not found !");
-        let mut raw_fat = Vec::new();
-        file.read_to_end(&mut raw_fat).expect("Something went wrong reading the file !");
-        for i in SECTOR_SIZE..(SECTOR_SIZE+self.fat_size()) {
-            self.fat.push(raw_fat[i]);
-        }
-    }
-    
-    pub fn set_entries(&mut self) {
-        unsafe {
-            let mut file = File::open(self.image.clone()).expect("File not found !");
-            
-            let mut cnt = 0;
-            while cnt < self.entries_size() {
-                let mut raw_name = [0;26];
-                file.seek(SeekFrom::Start((self.root_entry() + cnt) as u64)).expect("File seek failed !");
-                file.read(&mut raw_name).expect("Something went wrong reading the file !");
-                if raw_name[0] != 0 {
-                    let mut raw_start = [0;2];
-                    file.read(&mut raw_start).expect("Something went wrong reading the file !");
-                    let start = mem::transmute::<[u8;2], u16>(raw_start);
-                    let mut raw_size = [0;4];
-                    file.read(&mut raw_size).expect("Something went wrong reading the file !");
-                    let size = mem::transmute::<[u8;4], u32>(raw_size);
-                    self.entries.push(Entry { name: raw_name, start: start, size: size });
+    pub fn entries_size(&self) -> usize {
+        self.sb.root_blocks as usize * self.sb.block_size as usize * SECTOR_SIZE
+    }
+    
+    pub fn set_fat(&mut self) -> Result<()> {
+        let mut raw_fat = vec![0; self.fat_size()];
+        self.dev.read_bytes(SECTOR_SIZE as u64, &mut raw_fat)?;
+        self.fat = Fat::from_bytes(self.sb.fat_width(), raw_fat);
+        self.disk_fat = self.fat.clone();
+        Ok(())
+    }
+    
+    pub fn set_entries(&mut self, lenient: bool) -> Result<()> {
+        let mut raw_entries = vec![0; self.entries_size()];
+        let root_entry = self.root_entry();
+        self.dev.read_bytes(root_entry as u64, &mut raw_entries)?;
+        let mut entries = self.parse_entries(&raw_entries);
+        self.read_children(&mut entries, &mut Vec::new(), lenient)?;
+        self.entries = entries;
+        Ok(())
+    }
+    
+    pub fn parse_entries(&self, raw_entries: &[u8]) -> Vec<Entry> {
+        let mut entries: Vec<Entry> = Vec::new();
+        // rank of the last continuation entry of the last entry
+        let mut rank = 0;
+        // a trailing part shorter than an entry is left out, `check` reports the
+        // size of its directory
+        for raw_entry in raw_entries.chunks_exact(self.sb.entry_size()) {
+            if self.sb.has_long_names() && raw_entry[0] == LONG_NAME_MARKER {
+                // continuation entries out of order or without an entry are left out
+                if let (Some(entry), true) = (entries.last_mut(), raw_entry[1] as usize == rank + 1) {
+                    entry.name.extend(raw_entry[2..].iter().take_while(|&&byte| byte != 0));
+                    rank += 1;
+                }
+            } else if raw_entry[0] != 0 {
+                entries.push(Entry::from_bytes(raw_entry, &self.sb));
+                rank = 0;
+            }
+        }
+        entries
+    }
+    
+    // a lenient read leaves the children of unreadable directories empty
+    fn read_children(&mut self, entries: &mut [Entry], parents: &mut Vec<u32>, lenient: bool) -> Result<()> {
+        for entry in entries.iter_mut().filter(|e| e.is_dir()) {
+            // a directory containing one of its parents would never end
+            if parents.contains(&entry.start) {
+                if lenient {
+                    continue;
                 }
-                cnt += mem::size_of::<Entry>();
+                return Err(MicroFsError::CorruptChain(entry.start as usize));
+            }
+            let raw_entries = match self.get_blocks(entry).and_then(|blocks| self.read_data(&blocks, entry.size as usize)) {
+                Ok(raw_entries) => raw_entries,
+                Err(_) if lenient => continue,
+                Err(e) => return Err(e)
+            };
+            let mut children = self.parse_entries(&raw_entries);
+            parents.push(entry.start);
+            self.read_children(&mut children, parents, lenient)?;
+            parents.pop();
+            entry.children = children;
+        }
+        Ok(())
+    }
+    
+    pub fn dir_table(&self, entries: &[Entry]) -> Vec<u8> {
+        let mut raw_entries = Vec::new();
+        for entry in entries {
+            raw_entries.extend_from_slice(&entry.to_bytes(&self.sb));
+        }
+        raw_entries
+    }
+    
+    pub fn find(&self, path: &str) -> Result<&Entry> {
+        let parts = split_path(path);
+        let (last, parents) = match parts.split_last() {
+            Some(parts) => parts,
+            None => return Err(MicroFsError::NotFound(path.to_string()))
+        };
+        let mut entries = &self.entries;
+        for part in parents {
+            entries = match entries.iter().find(|e| e.filename() == *part) {
+                Some(e) if e.is_dir() => &e.children,
+                Some(_) => return Err(MicroFsError::NotADirectory(path.to_string())),
+                None => return Err(MicroFsError::NotFound(path.to_string()))
+            };
+        }
+        match entries.iter().find(|e| e.filename() == *last) {
+            Some(e) => Ok(e),
+            None => Err(MicroFsError::NotFound(path.to_string()))
+        }
+    }
+    
+    pub fn find_mut(&mut self, path: &str) -> Result<&mut Entry> {
+        let parts = split_path(path);
+        let (last, parents) = match parts.split_last() {
+            Some(parts) => parts,
+            None => return Err(MicroFsError::NotFound(path.to_string()))
+        };
+        let mut entries = &mut self.entries;
+        for part in parents {
+            entries = match entries.iter_mut().find(|e| e.filename() == *part) {
+                Some(e) if e.is_dir() => &mut e.children,
+                Some(_) => return Err(MicroFsError::NotADirectory(path.to_string())),
+                None => return Err(MicroFsError::NotFound(path.to_string()))
+            };
+        }
+        match entries.iter_mut().find(|e| e.filename() == *last) {
+            Some(e) => Ok(e),
+            None => Err(MicroFsError::NotFound(path.to_string()))
+        }
+    }
+    
+    // children of the directory at `path`, the root directory being `self.entries`
+    pub fn dir_entries(&self, path: &str) -> Result<&Vec<Entry>> {
+        if split_path(path).is_empty() {
+            return Ok(&self.entries);
+        }
+        let entry = self.find(path)?;
+        if !entry.is_dir() {
+            return Err(MicroFsError::NotADirectory(path.to_string()));
+        }
+        Ok(&entry.children)
+    }
+    
+    pub fn dir_entries_mut(&mut self, path: &str) -> Result<&mut Vec<Entry>> {
+        if split_path(path).is_empty() {
+            return Ok(&mut self.entries);
+        }
+        let entry = self.find_mut(path)?;
+        if !entry.is_dir() {
+            return Err(MicroFsError::NotADirectory(path.to_string()));
+        }
+        Ok(&mut entry.children)
+    }
+    
+    // make the table of the directory at `path` fit its entries, the root
+    // directory has a fixed size while the others grow and shrink their chain
+    pub fn resize_dir(&mut self, path: &str) -> Result<()> {
+        let size = self.sb.table_size(self.dir_entries(path)?);
+        if split_path(path).is_empty() {
+            if size > self.entries_size() {
+                return Err(MicroFsError::DirectoryFull);
             }
+            return Ok(());
+        }
+        let mut dir = {
+            let entry = self.find(path)?;
+            Entry { start: entry.start, size: entry.size, ..Entry::new("") }
+        };
+        self.resize_chain(&mut dir, size as u32)?;
+        self.find_mut(path)?.size = dir.size;
+        Ok(())
+    }
+    
+    pub fn insert_entry(&mut self, dir: &str, entry: Entry) -> Result<()> {
+        self.dir_entries_mut(dir)?.push(entry);
+        if let Err(e) = self.resize_dir(dir) {
+            self.dir_entries_mut(dir)?.pop();
+            return Err(e);
         }
+        Ok(())
+    }
+    
+    // detach every file (or directory) named like the last component of `path`
+    pub fn remove_entries(&mut self, path: &str, dir: bool) -> Result<Vec<Entry>> {
+        let (parent, name) = split_parent(path);
+        let entries = mem::take(self.dir_entries_mut(&parent)?);
+        let (removed, kept): (Vec<Entry>, Vec<Entry>) = entries.into_iter().partition(|e| e.filename() == name && e.is_dir() == dir);
+        *self.dir_entries_mut(&parent)? = kept;
+        self.resize_dir(&parent)?;
+        Ok(removed)
+    }
+    
+    // release the blocks of a detached entry and of everything below it
+    pub fn free_entry(&mut self, entry: &Entry) -> Result<()> {
+        for child in &entry.children {
+            self.free_entry(child)?;
+        }
+        let blocks = self.get_blocks(entry)?;
+        self.update_fat(&blocks, false);
+        self.pending.retain(|p| p.start != entry.start);
+        Ok(())
     }
     
     pub fn empty_blocks(&mut self, entry: &mut Entry) -> Vec<usize> {
-        let entries_blocks_size = self.entries_size() / (SECTOR_SIZE * (self.sb.block_size as usize));
-        let data_start = entries_blocks_size + (self.sb.root_entry as usize);
+        let count = entry.size as usize / (SECTOR_SIZE * self.sb.block_size as usize) + 1;
+        let blocks = self.find_free_blocks(count);
+        if let Some(&start) = blocks.first() {
+            entry.start = start as u32;
+        }
+        blocks
+    }
+    
+    // end of the data area, blocks past the largest encodable index cannot be chained
+    pub fn data_end(&self) -> usize {
+        cmp::min(self.sb.journal_start(), self.fat.max_block() + 1)
+    }
+    
+    pub fn find_free_blocks(&self, count: usize) -> Vec<usize> {
+        let data_start = self.sb.data_start();
+        let data_end = self.data_end();
         
-        let mut cnt = 0;
+        // blocks freed since the last save still hold saved data, they are only
+        // taken once the others run out so that an interrupted save keeps that data
         let mut blocks = Vec::new();
-        for i in data_start..self.fat.len() {
-            if (self.fat)[i] == 0xff {
-                if cnt == 0 {
-                    entry.start = i as u16;
-                }
-                blocks.push(i);
-                cnt += 1;
-            }
-            if cnt >= (entry.size as usize / (SECTOR_SIZE * self.sb.block_size as usize) + 1) {
+        let mut reused = Vec::new();
+        for i in data_start..data_end {
+            if blocks.len() >= count {
                 break;
             }
+            if self.fat.get(i) == FatEntry::Free {
+                if self.disk_fat.get(i) == FatEntry::Free {
+                    blocks.push(i);
+                } else {
+                    reused.push(i);
+                }
+            }
         }
-        return blocks;
+        let missing = count - blocks.len();
+        blocks.extend(reused.into_iter().take(missing));
+        blocks.sort();
+        blocks
     }
     
-    pub fn get_blocks(&mut self, entry: &mut Entry) -> Vec<usize> {
+    pub fn get_blocks(&self, entry: &Entry) -> Result<Vec<usize>> {
         let mut blocks = Vec::new();
         let mut block = entry.start as usize;
         blocks.push(block);
         loop {
-            block = self.fat[block] as usize;
-            if block != 0 {
-                blocks.push(block);
-            } else {
-                break;
+            // an index outside the FAT or a chain longer than the FAT means a broken or cyclic chain
+            if block >= self.fat.len() || blocks.len() > self.fat.len() {
+                return Err(MicroFsError::CorruptChain(block));
+            }
+            match self.fat.get(block) {
+                FatEntry::Next(next) => {
+                    block = next;
+                    blocks.push(block);
+                },
+                FatEntry::End => break,
+                FatEntry::Free => return Err(MicroFsError::CorruptChain(block))
             }
         }
-        return blocks;
+        Ok(blocks)
     }
     
-    pub fn update_fat(&mut self, blocks: &mut Vec<usize>, add: bool) {
+    // grow or shrink the chain of `entry` to hold `size` bytes
+    pub fn resize_chain(&mut self, entry: &mut Entry, size: u32) -> Result<()> {
+        let blocks = self.get_blocks(entry)?;
+        let needed = size as usize / (SECTOR_SIZE * self.sb.block_size as usize) + 1;
+        if needed > blocks.len() {
+            let extra = self.find_free_blocks(needed - blocks.len());
+            if extra.len() < needed - blocks.len() {
+                return Err(MicroFsError::NoSpace { needed: needed - blocks.len(), available: extra.len() });
+            }
+            let mut chain = blocks;
+            chain.extend_from_slice(&extra);
+            self.update_fat(&chain, true);
+        } else if needed < blocks.len() {
+            self.update_fat(&blocks[needed..], false);
+            self.update_fat(&blocks[..needed], true);
+        }
+        entry.size = size;
+        Ok(())
+    }
+    
+    pub fn update_fat(&mut self, blocks: &[usize], add: bool) {
         for i in 0..blocks.len() {
             if add {
                 if i == (blocks.len() - 1) {
-                    (self.fat)[blocks[i]] = 0;
+                    self.fat.set(blocks[i], FatEntry::End);
                 } else {
-                    (self.fat)[blocks[i]] = blocks[i+1] as u8;
+                    self.fat.set(blocks[i], FatEntry::Next(blocks[i+1]));
                 }
             } else {
-                (self.fat)[blocks[i]] = 0xff;
+                self.fat.set(blocks[i], FatEntry::Free);
             }
         }
     }
     
-    pub fn write_data(&mut self, blocks: &mut Vec<usize>, data: Vec<u8>) {
-        let mut file = OpenOptions::new().read(true).write(true).open(self.image.clone()).expect("File not found !");
-        let mut cnt = 0;
-        for block in blocks.iter() {
-            let offset = block * (self.sb.block_size as usize) * SECTOR_SIZE;
-            file.seek(SeekFrom::Start(offset as u64)).expect("File seek failed !");
-            
-            let data_block_start = cnt * (self.sb.block_size as usize) * SECTOR_SIZE;
-            let mut data_block_end = data_block_start + (self.sb.block_size as usize) * SECTOR_SIZE;
-            if data_block_end > data.len() {
-                data_block_end = data.len();
-            }
-            file.write_all(&(data[data_block_start..data_block_end])).expect("Failed to write in file!");
-            cnt += 1;
+    pub fn write_data(&mut self, blocks: &[usize], data: &[u8]) -> Result<()> {
+        let block_bytes = (self.sb.block_size as usize) * SECTOR_SIZE;
+        for (block, chunk) in blocks.iter().zip(data.chunks(block_bytes)) {
+            self.dev.write_bytes((block * block_bytes) as u64, chunk)?;
         }
+        Ok(())
     }
+    
+    pub fn read_data(&mut self, blocks: &[usize], size: usize) -> Result<Vec<u8>> {
+        let block_bytes = (self.sb.block_size as usize) * SECTOR_SIZE;
+        let mut data = vec![0; blocks.len() * block_bytes];
+        for (block, chunk) in blocks.iter().zip(data.chunks_mut(block_bytes)) {
+            self.dev.read_bytes((block * block_bytes) as u64, chunk)?;
+        }
+        data.truncate(size);
+        Ok(data)
+    }
+}
+
+// continue the CRC32 (IEEE) `crc` of some data over `data`, starting from 0
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
 }
 
+// seconds since the Unix epoch, 0 for earlier times
+pub fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+pub fn now() -> u64 {
+    unix_time(SystemTime::now())
+}
+
+pub fn split_path(path: &str) -> Vec<&str> {
+    path.split('/').filter(|part| !part.is_empty()).collect()
+}
+
+// "/fonts/small.bin" gives ("fonts", "small.bin"), an empty parent being the root directory
+pub fn split_parent(path: &str) -> (String, &str) {
+    let mut parts = split_path(path);
+    let name = parts.pop().unwrap_or("");
+    (parts.join("/"), name)
+}
+
+// "." and ".." would hide or shadow a directory, a NUL would end the name early
+// and a leading LONG_NAME_MARKER would pass for the continuation of another
+// name, control characters are refused altogether
+pub fn check_name(path: &str, name: &str) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.chars().any(char::is_control) {
+        return Err(MicroFsError::InvalidName(path.to_string()));
+    }
+    Ok(())
+}
+
+// the bytes up to the first NUL, or up to the first invalid UTF-8 sequence of
+// a damaged name, which `check` reports
 pub fn bytes_to_str(bytes: &[u8]) -> &str {
     let mut cnt = 0;
     for &byte in bytes {
@@ -121,5 +363,8 @@ pub fn bytes_to_str(bytes: &[u8]) -> &str {
         }
         cnt += 1;
     }
-    str::from_utf8(&bytes[0..cnt]).expect("Found invalid UTF-8")
+    match str::from_utf8(&bytes[0..cnt]) {
+        Ok(s) => s,
+        Err(e) => str::from_utf8(&bytes[0..e.valid_up_to()]).unwrap()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,61 @@
+use super::*;
+
+// a file whose data no longer gives the checksum of its entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub path: String,
+    pub expected: u32,
+    pub actual: u32
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub files: usize,
+    pub mismatches: Vec<Mismatch>
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl<D: BlockDevice> MicroFS<D> {
+    // recompute the checksum of every file of the image as saved, the files
+    // with data not saved yet are left out
+    pub fn verify(&mut self) -> Result<VerifyReport> {
+        if !self.sb.has_checksums() {
+            return Err(MicroFsError::Unsupported("directory entries without checksums"));
+        }
+        let mut report = VerifyReport::default();
+        let entries = self.entries.clone();
+        self.verify_entries(&entries, "", &mut report)?;
+        Ok(report)
+    }
+
+    fn verify_entries(&mut self, entries: &[Entry], parent: &str, report: &mut VerifyReport) -> Result<()> {
+        for entry in entries {
+            let path = format!("{}/{}", parent, entry.filename());
+            if entry.is_dir() {
+                self.verify_entries(&entry.children, &path, report)?;
+                continue;
+            }
+            if self.pending.iter().any(|p| p.start == entry.start) {
+                continue;
+            }
+            report.files += 1;
+            let actual = self.file_checksum(entry)?;
+            if actual != entry.checksum {
+                report.mismatches.push(Mismatch { path, expected: entry.checksum, actual });
+            }
+        }
+        Ok(())
+    }
+
+    // CRC32 of the data of `entry` in its blocks
+    pub(crate) fn file_checksum(&mut self, entry: &Entry) -> Result<u32> {
+        let blocks = self.get_blocks(entry)?;
+        let data = self.read_data(&blocks, entry.size as usize)?;
+        Ok(crc32(0, &data))
+    }
+}
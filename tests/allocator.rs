@@ -0,0 +1,109 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+
+// an image with free runs of 3, 5 and 6 blocks starting at blocks 4, 8 and 14
+fn fragmented(name: &str) -> MicroFS<MemDevice> {
+    let mut sb = SuperBlock::new("test", 1, 20 * 512);
+    sb.journal_blocks = 0;
+    let mut test_fs = MicroFS::format_device(MemDevice::new(20), sb).unwrap();
+    for &(file, size) in &[("a", 10), ("b", 1200), ("c", 10), ("d", 2200), ("e", 10)] {
+        fs::write(name, vec![1; size]).unwrap();
+        test_fs.add_as(name, &format!("/{}", file)).unwrap();
+    }
+    test_fs.del("/b").unwrap();
+    test_fs.del("/d").unwrap();
+    test_fs.save().unwrap();
+    fs::remove_file(name).expect("Failed removing the file");
+    test_fs
+}
+
+fn blocks<D: BlockDevice>(test_fs: &MicroFS<D>, path: &str) -> Vec<usize> {
+    let entry = test_fs.find(path).unwrap().clone();
+    test_fs.get_blocks(&entry).unwrap()
+}
+
+#[test]
+fn policies() {
+    let mut test_fs = fragmented("alloc_policies_frag_test.bin");
+    assert_eq!(test_fs.free_runs(false), vec![(4, 3), (8, 5), (14, 6)]);
+    assert_eq!(test_fs.largest_free_run(), 6);
+
+    fs::write("alloc_policies_test.bin", [2; 1800]).unwrap();
+    test_fs.add_with("alloc_policies_test.bin", "/first", AllocPolicy::FirstFit).unwrap();
+    assert_eq!(blocks(&test_fs, "/first"), vec![4, 5, 6, 8]);
+    test_fs.del("/first").unwrap();
+    test_fs.add_with("alloc_policies_test.bin", "/best", AllocPolicy::BestFit).unwrap();
+    assert_eq!(blocks(&test_fs, "/best"), vec![8, 9, 10, 11]);
+    test_fs.del("/best").unwrap();
+    test_fs.add_with("alloc_policies_test.bin", "/contiguous", AllocPolicy::Contiguous).unwrap();
+    assert_eq!(blocks(&test_fs, "/contiguous"), vec![8, 9, 10, 11]);
+    test_fs.del("/contiguous").unwrap();
+    // the path the file got is given back, a directory keeps the host name
+    let target = test_fs.add_with("alloc_policies_test.bin", "/", AllocPolicy::BestFit).unwrap();
+    assert_eq!(target, "/alloc_policies_test.bin");
+    assert_eq!(test_fs.replace_with("alloc_policies_test.bin", "/", AllocPolicy::BestFit).unwrap(), target);
+    test_fs.del(&target).unwrap();
+
+    // no run holds 7 blocks
+    fs::write("alloc_policies_test.bin", [3; 3200]).unwrap();
+    match test_fs.add_with("alloc_policies_test.bin", "/f", AllocPolicy::Contiguous) {
+        Err(MicroFsError::NotContiguous { needed, largest }) => {
+            assert_eq!(needed, 7);
+            assert_eq!(largest, 6);
+        },
+        res => panic!("unexpected result {:?}", res)
+    }
+    assert!(test_fs.find("/f").is_err());
+    test_fs.add_with("alloc_policies_test.bin", "/f", AllocPolicy::BestFit).unwrap();
+    assert_eq!(blocks(&test_fs, "/f"), vec![4, 5, 6, 8, 9, 10, 11]);
+    test_fs.save().unwrap();
+    fs::remove_file("alloc_policies_test.bin").expect("Failed removing the file");
+
+    let mut data = Vec::new();
+    test_fs.extract("/f", &mut data).unwrap();
+    assert_eq!(data, vec![3; 3200]);
+    assert!(test_fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn freed_blocks_last() {
+    let mut test_fs = fragmented("alloc_freed_frag_test.bin");
+    // the block of /c still holds saved data until the next save, it is only
+    // taken when no other run is large enough
+    test_fs.del("/c").unwrap();
+    assert_eq!(test_fs.free_runs(true), vec![(4, 9), (14, 6)]);
+    fs::write("alloc_freed_test.bin", [4; 3200]).unwrap();
+    test_fs.add_with("alloc_freed_test.bin", "/f", AllocPolicy::BestFit).unwrap();
+    assert_eq!(blocks(&test_fs, "/f"), (4..11).collect::<Vec<_>>());
+    test_fs.del("/f").unwrap();
+    fs::write("alloc_freed_test.bin", [4; 2000]).unwrap();
+    test_fs.add_with("alloc_freed_test.bin", "/f", AllocPolicy::Contiguous).unwrap();
+    // with the block of /c, the best run would have been the one of 6 blocks at 14
+    assert_eq!(blocks(&test_fs, "/f"), (8..12).collect::<Vec<_>>());
+    fs::remove_file("alloc_freed_test.bin").expect("Failed removing the file");
+}
+
+#[test]
+fn replace_contiguous() {
+    let mut test_fs = fragmented("alloc_replace_frag_test.bin");
+    fs::write("alloc_replace_test.bin", [5; 2200]).unwrap();
+    test_fs.replace_with("alloc_replace_test.bin", "/a", AllocPolicy::Contiguous).unwrap();
+    assert_eq!(blocks(&test_fs, "/a"), vec![8, 9, 10, 11, 12]);
+    assert_eq!(test_fs.fat.get(3), FatEntry::Free);
+    test_fs.save().unwrap();
+
+    // a failed replace leaves the file as it was
+    fs::write("alloc_replace_test.bin", [6; 4000]).unwrap();
+    match test_fs.replace_with("alloc_replace_test.bin", "/a", AllocPolicy::Contiguous) {
+        Err(MicroFsError::NotContiguous { .. }) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    fs::remove_file("alloc_replace_test.bin").expect("Failed removing the file");
+    assert_eq!(blocks(&test_fs, "/a"), vec![8, 9, 10, 11, 12]);
+    let mut data = Vec::new();
+    test_fs.extract("/a", &mut data).unwrap();
+    assert_eq!(data, vec![5; 2200]);
+    assert!(test_fs.check().unwrap().is_clean());
+}
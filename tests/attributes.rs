@@ -0,0 +1,105 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+use std::io::Write;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn image() -> MicroFS<MemDevice> {
+    let mut sb = SuperBlock::new("test", 1, 200 * 512);
+    sb.version |= VERSION_ATTRIBUTES;
+    MicroFS::format_device(MemDevice::new(200), sb).unwrap()
+}
+
+#[test]
+fn round_trip() {
+    let mut test_fs = image();
+    assert_eq!(test_fs.sb.entry_size(), EXTENDED_ENTRY_SIZE);
+    fs::write("attributes_round_trip_test.bin", [1; 1000]).unwrap();
+    test_fs.add_as("attributes_round_trip_test.bin", "/a").unwrap();
+    fs::remove_file("attributes_round_trip_test.bin").expect("Failed removing the file");
+    test_fs.mkdir("/d").unwrap();
+    test_fs.touch("/d/b", Some(1_000_000_000)).unwrap();
+    test_fs.set_attributes("/a", ATTR_HIDDEN | ATTR_SYSTEM).unwrap();
+    test_fs.save().unwrap();
+
+    let mut test_fs = MicroFS::open_device(MemDevice::from_vec(test_fs.dev.into_inner())).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    let a = test_fs.list("/a").unwrap().remove(0);
+    assert_eq!(a.attributes, ATTR_HIDDEN | ATTR_SYSTEM);
+    let b = test_fs.list("/d/b").unwrap().remove(0);
+    assert_eq!((b.created, b.modified, b.size), (1_000_000_000, 1_000_000_000, 0));
+    let mut data = Vec::new();
+    test_fs.extract("/a", &mut data).unwrap();
+    assert_eq!(data, vec![1; 1000]);
+
+    // writing through a handle moves the modification time only
+    test_fs.open_file("/d/b", OpenMode::Write).unwrap().write_all(b"data").unwrap();
+    let b = test_fs.list("/d/b").unwrap().remove(0);
+    assert_eq!(b.created, 1_000_000_000);
+    assert!(b.modified > 1_000_000_000);
+}
+
+#[test]
+fn host_metadata() {
+    let mut test_fs = image();
+    fs::write(".attributes_host_test.bin", [1; 10]).unwrap();
+    let file = fs::File::options().write(true).open(".attributes_host_test.bin").unwrap();
+    file.set_modified(UNIX_EPOCH + Duration::from_secs(1_234_567_890)).unwrap();
+    let mut permissions = file.metadata().unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(".attributes_host_test.bin", permissions).unwrap();
+    test_fs.add(".attributes_host_test.bin").unwrap();
+    fs::remove_file(".attributes_host_test.bin").expect("Failed removing the file");
+
+    let entry = test_fs.list("/.attributes_host_test.bin").unwrap().remove(0);
+    assert_eq!(entry.modified, 1_234_567_890);
+    assert_eq!(entry.attributes & (ATTR_READ_ONLY | ATTR_HIDDEN), ATTR_READ_ONLY | ATTR_HIDDEN);
+}
+
+#[test]
+fn read_only() {
+    let mut test_fs = image();
+    test_fs.touch("/a", None).unwrap();
+    test_fs.set_attributes("/a", ATTR_READ_ONLY).unwrap();
+    match test_fs.del("/a") {
+        Err(MicroFsError::ReadOnly(name)) => assert_eq!(name, "/a"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.open_file("/a", OpenMode::Write) {
+        Err(MicroFsError::ReadOnly(_)) => (),
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+    fs::write("attributes_read_only_test.bin", [1; 10]).unwrap();
+    match test_fs.replace("attributes_read_only_test.bin", "/a") {
+        Err(MicroFsError::ReadOnly(_)) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    fs::remove_file("attributes_read_only_test.bin").expect("Failed removing the file");
+    test_fs.open_file("/a", OpenMode::Read).unwrap();
+
+    test_fs.set_attributes("/a", 0).unwrap();
+    test_fs.del("/a").unwrap();
+}
+
+#[test]
+fn old_layout() {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(100), SuperBlock::new("test", 1, 100 * 512)).unwrap();
+    assert_eq!(test_fs.sb.entry_size(), ENTRY_SIZE);
+    match test_fs.touch("/a", None) {
+        Err(MicroFsError::Unsupported(_)) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.set_attributes("/a", ATTR_HIDDEN) {
+        Err(MicroFsError::Unsupported(_)) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+
+    // the layout of the entries survives a resize that widens the FAT
+    let mut test_fs = image();
+    test_fs.touch("/a", Some(42)).unwrap();
+    test_fs.resize(400 * 512).unwrap();
+    assert_eq!(test_fs.sb.version, VERSION_FAT16 | VERSION_ATTRIBUTES);
+    let test_fs = MicroFS::open_device(MemDevice::from_vec(test_fs.dev.into_inner())).unwrap();
+    assert_eq!(test_fs.list("/a").unwrap()[0].modified, 42);
+}
@@ -2,7 +2,6 @@ extern crate micro_fs;
 use micro_fs::*;
 use micro_fs::utils::bytes_to_str;
 
-use std::mem;
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
@@ -10,9 +9,12 @@ use std::io::SeekFrom;
 
 #[test]
 fn constructors() {
-    let mut test_fs = MicroFS::new("fs1_test.img");
-    test_fs.sb = SuperBlock::new("test", 1, 100000);
-    assert_eq!(test_fs.image, "fs1_test.img");
+    match MicroFS::open("fs1_missing_test.img") {
+        Err(MicroFsError::Io(_)) => (),
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+    
+    let test_fs = MicroFS::format("fs1_constructors_test.img", "test", 1, 100000).unwrap();
     assert_eq!(test_fs.sb.sector_size, SECTOR_SIZE as u16);
     assert_eq!(test_fs.sb.block_size, 1);
     assert_eq!(test_fs.sb.fat_size, 195);
@@ -20,125 +22,292 @@ fn constructors() {
     assert_eq!(test_fs.sb.root_entry, 2);
     assert_eq!(test_fs.sb.label, [b't', b'e', b's', b't', b'\0', b'\0', b'\0', b'\0']);
     assert_eq!(test_fs.sb.signature, MAGIC);
+    assert_eq!(test_fs.fat.as_bytes(), &[0xff; 195][..]);
+    
+    let test_fs = MicroFS::open("fs1_constructors_test.img").unwrap();
+    assert_eq!(test_fs.sb, SuperBlock::new("test", 1, 100000));
+    assert!(test_fs.entries.is_empty());
+    
+    match MicroFS::format("fs1_constructors_test.img", "test", 0, 100000) {
+        Err(MicroFsError::BadSuperBlock(_)) => (),
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+    
+    fs::remove_file("fs1_constructors_test.img").expect("Failed removing the file");
 }
 
 #[test]
 fn create() {
-    let mut test_fs = MicroFS::new("fs1_test.img");
-    test_fs.create("test", 1, 100000);
+    MicroFS::format("fs1_create_test.img", "test", 1, 100000).unwrap();
     let mut raw_sb = [0; SECTOR_SIZE];
-    let mut file = File::open(test_fs.image.clone()).expect("File not found !");
-    file.read(&mut raw_sb).expect("Something went wrong reading the file !");
+    let mut file = File::open("fs1_create_test.img").expect("File not found !");
+    file.read_exact(&mut raw_sb).expect("Something went wrong reading the file !");
     
-    let signature = unsafe { mem::transmute::<[u8;2], u16>([raw_sb[510], raw_sb[511]]) };
+    let signature = u16::from_le_bytes([raw_sb[510], raw_sb[511]]);
     assert_eq!(signature, MAGIC);
     let bs = raw_sb[13];
     assert_eq!(bs, 1);
     let raw_label = &raw_sb[82..90];
     let label = bytes_to_str(raw_label);
     assert_eq!(label, "test");
-    let size = fs::metadata(test_fs.image.clone()).expect("Failed getting metadata!").len() as usize;
+    let size = fs::metadata("fs1_create_test.img").expect("Failed getting metadata!").len() as usize;
     assert_eq!(size, 99840);
     
     let mut raw_fat = [0; 195];
-    file.read(&mut raw_fat).expect("Something went wrong reading the file !");
+    file.read_exact(&mut raw_fat).expect("Something went wrong reading the file !");
     assert_eq!(&raw_fat[..], &[0xff; 195][..]);
     
     file.seek(SeekFrom::Start(1024)).expect("File seek failed !");
     let mut raw_entries = [0; 512];
-    file.read(&mut raw_entries).expect("Something went wrong reading the file !");
+    file.read_exact(&mut raw_entries).expect("Something went wrong reading the file !");
     assert_eq!(&raw_entries[..], &[0; 512][..]);
     
-    fs::remove_file("fs1_test.img").expect("Failed removing the file");
+    fs::remove_file("fs1_create_test.img").expect("Failed removing the file");
+}
+
+#[test]
+fn super_block() {
+    let sb = SuperBlock::new("test", 1, 100000);
+    let raw_sb = sb.to_bytes();
+    assert_eq!(SuperBlock::from_bytes(&raw_sb).unwrap(), sb);
+    
+    MicroFS::format("fs1_sb_test.img", "test", 1, 100000).unwrap();
+    let mut raw_image = fs::read("fs1_sb_test.img").unwrap();
+    assert_eq!(&raw_image[..SECTOR_SIZE], &raw_sb[..]);
+    
+    // padding the image does not change its geometry
+    raw_image.extend_from_slice(&[0; 4 * SECTOR_SIZE]);
+    fs::write("fs1_sb_test.img", &raw_image).unwrap();
+    let test_fs = MicroFS::open("fs1_sb_test.img").unwrap();
+    assert_eq!(test_fs.sb, sb);
+    assert_eq!(test_fs.fat.len(), 195);
+    
+    // truncating it makes it invalid
+    raw_image.truncate(50000);
+    fs::write("fs1_sb_test.img", &raw_image).unwrap();
+    match MicroFS::open("fs1_sb_test.img") {
+        Err(MicroFsError::BadSuperBlock(_)) => (),
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+    
+    let mut raw_bad = raw_sb;
+    raw_bad[44] += 1;
+    match SuperBlock::from_bytes(&raw_bad) {
+        Err(MicroFsError::BadSuperBlock(_)) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    
+    fs::remove_file("fs1_sb_test.img").expect("Failed removing the file");
 }
 
 #[test]
 fn add() {
-    let mut test_fs = MicroFS::new("fs1_test.img");
-    test_fs.create("test", 1, 100000);
-    test_fs.add("tests/test1.txt");
+    let mut test_fs = MicroFS::format("fs1_add_test.img", "test", 1, 100000).unwrap();
+    test_fs.add("tests/test1.txt").unwrap();
     
-    assert_eq!(bytes_to_str(&test_fs.new_entries[0].name), "tests/test1.txt");
-    assert_eq!(test_fs.new_entries[0].start, 3);
-    assert_eq!(test_fs.new_entries[0].size, fs::metadata("tests/test1.txt").expect("Failed getting metadata!").len() as u32);
+    assert_eq!(bytes_to_str(&test_fs.entries[0].name), "test1.txt");
+    assert_eq!(test_fs.entries[0].start, 3);
+    assert_eq!(test_fs.entries[0].size, fs::metadata("tests/test1.txt").expect("Failed getting metadata!").len() as u32);
+    assert_eq!(test_fs.pending[0].start, 3);
+    assert_eq!(test_fs.pending[0].data, fs::read("tests/test1.txt").unwrap());
     
-    fs::remove_file("fs1_test.img").expect("Failed removing the file");
+    fs::remove_file("fs1_add_test.img").expect("Failed removing the file");
 }
 
 #[test]
 fn save() {
-    let mut cmp_fs =  MicroFS {
-        image: "fs1_test.img".to_string(),
-        sb: SuperBlock::new("test", 1, 100000),
-        fat: Vec::new(),
-        entries:  Vec::new(),
-        new_entries: Vec::new()
-    };
-    
-    let mut test_fs = MicroFS::new("fs1_test.img");
-    test_fs.create("test", 1, 100000);
-    test_fs.add("tests/test1.txt");
-    test_fs.save();
-    
-    cmp_fs.set_fat();
+    let mut test_fs = MicroFS::format("fs1_save_test.img", "test", 1, 100000).unwrap();
+    test_fs.add("tests/test1.txt").unwrap();
+    test_fs.save().unwrap();
+    
+    let cmp_fs = MicroFS::open("fs1_save_test.img").unwrap();
     assert_eq!(test_fs.fat, cmp_fs.fat);
     
-    cmp_fs.set_entries();
-    assert_eq!(test_fs.new_entries[0].start, cmp_fs.entries[0].start);
-    assert_eq!(test_fs.new_entries[0].size, cmp_fs.entries[0].size);
+    assert_eq!(test_fs.entries[0].start, cmp_fs.entries[0].start);
+    assert_eq!(test_fs.entries[0].size, cmp_fs.entries[0].size);
+    assert!(test_fs.pending.is_empty());
     
     let mut raw_data = [0; SECTOR_SIZE];
-    let mut file = File::open(test_fs.image.clone()).expect("File not found !");
+    let mut file = File::open("fs1_save_test.img").expect("File not found !");
     file.seek(SeekFrom::Start(1536)).expect("File seek failed !");
-    file.read(&mut raw_data).expect("Something went wrong reading the file !");
+    file.read_exact(&mut raw_data).expect("Something went wrong reading the file !");
     for i in 0..SECTOR_SIZE {
         assert_eq!(raw_data[i], TEST1[i]);
     }
     
-    fs::remove_file("fs1_test.img").expect("Failed removing the file");
+    fs::remove_file("fs1_save_test.img").expect("Failed removing the file");
 }
 
 #[test]
 fn remove() {
-    let mut cmp_fs =  MicroFS {
-        image: "fs1_test.img".to_string(),
-        sb: SuperBlock::new("test", 1, 100000),
-        fat: Vec::new(),
-        entries:  Vec::new(),
-        new_entries: Vec::new()
-    };
-    
-    let mut test_fs = MicroFS::new("fs1_test.img");
-    test_fs.create("test", 1, 100000);
-    test_fs.add("tests/test1.txt");
-    test_fs.save();
-    test_fs.del("tests/test1.txt");
-    test_fs.save();
-    
-    cmp_fs.set_fat();
-    assert_eq!(&cmp_fs.fat[..], &[0xff; 195][..]);
-    
-    cmp_fs.set_entries();
-    assert_eq!(cmp_fs.new_entries.len(), 0);
-    
-    fs::remove_file("fs1_test.img").expect("Failed removing the file");
+    let mut test_fs = MicroFS::format("fs1_remove_test.img", "test", 1, 100000).unwrap();
+    test_fs.add("tests/test1.txt").unwrap();
+    test_fs.save().unwrap();
+    test_fs.del("test1.txt").unwrap();
+    test_fs.save().unwrap();
+    
+    let cmp_fs = MicroFS::open("fs1_remove_test.img").unwrap();
+    assert_eq!(cmp_fs.fat.as_bytes(), &[0xff; 195][..]);
+    assert_eq!(cmp_fs.entries.len(), 0);
+    
+    fs::remove_file("fs1_remove_test.img").expect("Failed removing the file");
+}
+
+#[test]
+fn host_changes() {
+    // the data is captured by add, the host file can change or go away before save
+    fs::write("fs1_host_test.txt", [1; 700]).unwrap();
+    let mut test_fs = MicroFS::format("fs1_host_test.img", "test", 1, 100000).unwrap();
+    test_fs.add("fs1_host_test.txt").unwrap();
+    fs::write("fs1_host_test.txt", [2; 3000]).unwrap();
+    fs::remove_file("fs1_host_test.txt").expect("Failed removing the file");
+    test_fs.save().unwrap();
+    
+    let mut test_fs = MicroFS::open("fs1_host_test.img").unwrap();
+    let mut data = Vec::new();
+    test_fs.extract("fs1_host_test.txt", &mut data).unwrap();
+    assert_eq!(data, vec![1; 700]);
+    
+    fs::remove_file("fs1_host_test.img").expect("Failed removing the file");
 }
 
 #[test]
 fn multiple_blocks() {
-    let mut test_fs = MicroFS::new("fs1_test.img");
-    test_fs.create("test", 1, 100000);
-    test_fs.add("tests/test1.txt");
-    test_fs.add("tests/test2.txt");
-    test_fs.save();
+    let mut test_fs = MicroFS::format("fs1_multiple_blocks_test.img", "test", 1, 100000).unwrap();
+    test_fs.add("tests/test1.txt").unwrap();
+    test_fs.add("tests/test2.txt").unwrap();
+    test_fs.save().unwrap();
     
     let mut raw_data = [0; SECTOR_SIZE*5];
-    let mut file = File::open(test_fs.image.clone()).expect("File not found !");
+    let mut file = File::open("fs1_multiple_blocks_test.img").expect("File not found !");
     file.seek(SeekFrom::Start(2048)).expect("File seek failed !");
-    file.read(&mut raw_data).expect("Something went wrong reading the file !");
+    file.read_exact(&mut raw_data).expect("Something went wrong reading the file !");
     
     assert_eq!(&raw_data[..], &TEST2[..]);
-    fs::remove_file("fs1_test.img").expect("Failed removing the file");
+    fs::remove_file("fs1_multiple_blocks_test.img").expect("Failed removing the file");
+}
+
+#[test]
+fn extract() {
+    let mut test_fs = MicroFS::format("fs1_extract_test.img", "test", 1, 100000).unwrap();
+    test_fs.add("tests/test2.txt").unwrap();
+    test_fs.save().unwrap();
+    
+    let mut test_fs = MicroFS::open("fs1_extract_test.img").unwrap();
+    let mut data = Vec::new();
+    test_fs.extract("test2.txt", &mut data).unwrap();
+    let mut expected = Vec::new();
+    File::open("tests/test2.txt").expect("File not found !").read_to_end(&mut expected).expect("Something went wrong reading the file !");
+    assert_eq!(data, expected);
+    
+    // a file not saved yet reads back the same
+    test_fs.add_as("tests/test1.txt", "/test1.txt").unwrap();
+    let mut data = Vec::new();
+    test_fs.extract("test1.txt", &mut data).unwrap();
+    assert_eq!(data, fs::read("tests/test1.txt").unwrap());
+    
+    fs::remove_file("fs1_extract_test.img").expect("Failed removing the file");
+}
+
+#[test]
+fn errors() {
+    // 27 blocks, 7 of them for the journal
+    let mut test_fs = MicroFS::format("fs1_errors_test.img", "test", 1, 14000).unwrap();
+    
+    match test_fs.add("tests/missing.txt") {
+        Err(MicroFsError::NotFound(name)) => assert_eq!(name, "tests/missing.txt"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.del("missing.txt") {
+        Err(MicroFsError::NotFound(name)) => assert_eq!(name, "missing.txt"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.extract("missing.txt", &mut Vec::new()) {
+        Err(MicroFsError::NotFound(name)) => assert_eq!(name, "missing.txt"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    for i in 0..3 {
+        test_fs.add_as("tests/test2.txt", &format!("/test2_{}.txt", i)).unwrap();
+    }
+    match test_fs.add_as("tests/test2.txt", "/test2_0.txt") {
+        Err(MicroFsError::DuplicateName(name)) => assert_eq!(name, "/test2_0.txt"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.add("tests/test2.txt") {
+        Err(MicroFsError::NoSpace { needed, available }) => {
+            assert_eq!(needed, 5);
+            assert_eq!(available, 2);
+        },
+        res => panic!("unexpected result {:?}", res)
+    }
+    
+    fs::write("fs1_errors_test.img", [0; SECTOR_SIZE]).unwrap();
+    match MicroFS::open("fs1_errors_test.img") {
+        Err(MicroFsError::BadSignature(signature)) => assert_eq!(signature, 0),
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+    
+    fs::remove_file("fs1_errors_test.img").expect("Failed removing the file");
+}
+
+#[test]
+fn corrupt_chain() {
+    let mut test_fs = MicroFS::format("fs1_chain_test.img", "test", 1, 100000).unwrap();
+    test_fs.add("tests/test2.txt").unwrap();
+    
+    let entry = test_fs.entries[0].clone();
+    let start = entry.start as usize;
+    test_fs.fat.set(start, FatEntry::Next(start));
+    match test_fs.get_blocks(&entry) {
+        Err(MicroFsError::CorruptChain(_)) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    test_fs.fat.set(start, FatEntry::Next(0xfe));
+    match test_fs.get_blocks(&entry) {
+        Err(MicroFsError::CorruptChain(block)) => assert_eq!(block, 0xfe),
+        res => panic!("unexpected result {:?}", res)
+    }
+    
+    fs::remove_file("fs1_chain_test.img").expect("Failed removing the file");
+}
+
+#[test]
+fn root_directory() {
+    fs::create_dir_all("fs1_root_test").unwrap();
+    for i in 0..33 {
+        fs::write(format!("fs1_root_test/f{}", i), [i as u8; 10]).unwrap();
+    }
+    
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    for i in 0..16 {
+        test_fs.add(&format!("fs1_root_test/f{}", i)).unwrap();
+    }
+    match test_fs.add("fs1_root_test/f16") {
+        Err(MicroFsError::DirectoryFull) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    
+    let mut sb = SuperBlock::new("test", 1, 100000);
+    sb.root_blocks = 2;
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), sb).unwrap();
+    for i in 0..32 {
+        test_fs.add(&format!("fs1_root_test/f{}", i)).unwrap();
+    }
+    assert_eq!(test_fs.entries[0].start, 4);
+    match test_fs.add("fs1_root_test/f32") {
+        Err(MicroFsError::DirectoryFull) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    test_fs.save().unwrap();
+    
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert_eq!(test_fs.sb.root_blocks, 2);
+    assert_eq!(test_fs.entries.len(), 32);
+    let mut data = Vec::new();
+    test_fs.extract("f31", &mut data).unwrap();
+    assert_eq!(data, [31; 10]);
+    
+    fs::remove_dir_all("fs1_root_test").expect("Failed removing the directory");
 }
 
 const TEST1 : [u8;SECTOR_SIZE] = [
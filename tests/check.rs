@@ -0,0 +1,137 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+
+fn image(host: &str, sizes: &[usize]) -> MicroFS<MemDevice> {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    for (i, &size) in sizes.iter().enumerate() {
+        fs::write(format!("{}{}", host, i), vec![i as u8; size]).unwrap();
+        test_fs.add_as(&format!("{}{}", host, i), &format!("/f{}", i)).unwrap();
+    }
+    test_fs.save().unwrap();
+    for i in 0..sizes.len() {
+        fs::remove_file(format!("{}{}", host, i)).expect("Failed removing the file");
+    }
+    MicroFS::open_device(test_fs.dev).unwrap()
+}
+
+#[test]
+fn clean() {
+    let mut test_fs = image("check_clean_test.bin", &[2000, 10]);
+    test_fs.mkdir("/d").unwrap();
+    let report = test_fs.check().unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.files, 2);
+    assert_eq!(report.directories, 1);
+    assert_eq!(report.used_blocks, 6);
+}
+
+#[test]
+fn orphan_and_cycle() {
+    let mut test_fs = image("check_cycle_test.bin", &[2000]);
+    assert_eq!(test_fs.entries[0].start, 3);
+    test_fs.fat.set(100, FatEntry::End);
+    test_fs.fat.set(4, FatEntry::Next(3));
+    let expected = vec![
+        Problem::Cycle { path: "/f0".to_string(), block: 3 },
+        Problem::SizeMismatch { path: "/f0".to_string(), blocks: 2, expected: 4 },
+        Problem::Orphan(5),
+        Problem::Orphan(6),
+        Problem::Orphan(100)
+    ];
+    assert_eq!(test_fs.check().unwrap().problems, expected);
+    assert_eq!(test_fs.repair().unwrap().problems, expected);
+
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    assert_eq!(test_fs.entries[0].size, 1023);
+    assert_eq!(test_fs.fat.get(4), FatEntry::End);
+    assert_eq!(test_fs.fat.get(100), FatEntry::Free);
+}
+
+#[test]
+fn cross_linked_and_reserved() {
+    let mut test_fs = image("check_cross_test.bin", &[1200, 10, 10]);
+    test_fs.entries[1].start = test_fs.entries[0].start;
+    test_fs.entries[2].start = 1;
+    let report = test_fs.check().unwrap();
+    assert_eq!(report.problems, vec![
+        Problem::CrossLinked { path: "/f1".to_string(), block: 3 },
+        Problem::ReservedBlock { path: "/f2".to_string(), block: 1 },
+        Problem::Orphan(6),
+        Problem::Orphan(7)
+    ]);
+    test_fs.repair().unwrap();
+
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    assert_eq!(test_fs.entries.len(), 1);
+    let mut data = Vec::new();
+    test_fs.extract("/f0", &mut data).unwrap();
+    assert_eq!(data, vec![0; 1200]);
+}
+
+#[test]
+fn bad_signature_and_directory_cycle() {
+    let mut test_fs = image("check_signature_test.bin", &[]);
+    test_fs.mkdir("/a").unwrap();
+    test_fs.mkdir("/a/b").unwrap();
+    test_fs.save().unwrap();
+
+    // make /a/b point back to /a and wipe the signature
+    let dir = test_fs.find("/a").unwrap().clone();
+    let mut children = dir.children.clone();
+    children[0].start = dir.start;
+    let raw_entries = test_fs.dir_table(&children);
+    let blocks = test_fs.get_blocks(&dir).unwrap();
+    test_fs.write_data(&blocks, &raw_entries).unwrap();
+    let mut raw_image = test_fs.dev.into_inner();
+    raw_image[510] = 0;
+    raw_image[511] = 0;
+
+    match MicroFS::open_device(MemDevice::from_vec(raw_image.clone())) {
+        Err(MicroFsError::BadSignature(signature)) => assert_eq!(signature, 0),
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+    let mut test_fs = MicroFS::open_device_unchecked(MemDevice::from_vec(raw_image)).unwrap();
+    assert_eq!(test_fs.check().unwrap().problems, vec![
+        Problem::BadSignature(0),
+        Problem::CrossLinked { path: "/a/b".to_string(), block: 3 },
+        Problem::Orphan(4)
+    ]);
+    test_fs.repair().unwrap();
+
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    assert!(test_fs.list("/a").unwrap().is_empty());
+}
+
+#[test]
+fn bad_name_and_short_table() {
+    let mut test_fs = image("check_name_test.bin", &[10]);
+    test_fs.mkdir("/d").unwrap();
+    fs::write("check_name_test.bin", [1; 10]).unwrap();
+    test_fs.add_as("check_name_test.bin", "/d/g").unwrap();
+    fs::remove_file("check_name_test.bin").expect("Failed removing the file");
+    test_fs.save().unwrap();
+
+    // a name that is not UTF-8 and a directory size cutting its table short
+    let root = test_fs.sb.root_entry as usize * SECTOR_SIZE;
+    let mut raw_image = test_fs.dev.into_inner();
+    raw_image[root + 1] = 0xff;
+    raw_image[root + ENTRY_SIZE + 28..root + ENTRY_SIZE + 32].copy_from_slice(&48u32.to_le_bytes());
+
+    let mut test_fs = MicroFS::open_device_unchecked(MemDevice::from_vec(raw_image)).unwrap();
+    assert_eq!(test_fs.check().unwrap().problems, vec![
+        Problem::BadName("/f".to_string()),
+        Problem::SizeMismatch { path: "/d".to_string(), blocks: 1, expected: 1 }
+    ]);
+    test_fs.repair().unwrap();
+
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    let names: Vec<String> = test_fs.list("/").unwrap().into_iter().map(|e| e.name).collect();
+    assert_eq!(names, vec!["f_".to_string(), "d".to_string()]);
+    assert_eq!(test_fs.find("/d").unwrap().size as usize, ENTRY_SIZE);
+}
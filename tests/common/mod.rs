@@ -0,0 +1,72 @@
+// fixtures shared by the tests of operations that must survive a failure midway
+#![allow(dead_code)]
+
+use micro_fs::*;
+
+use std::io;
+
+// a device that fails every write once `writes_left` reaches 0, as if the machine went down
+pub struct FailingDevice {
+    pub inner: MemDevice,
+    pub writes_left: Option<usize>,
+    pub writes: usize
+}
+impl FailingDevice {
+    pub fn new(raw_image: Vec<u8>, writes_left: Option<usize>) -> FailingDevice {
+        FailingDevice { inner: MemDevice::from_vec(raw_image), writes_left, writes: 0 }
+    }
+}
+impl BlockDevice for FailingDevice {
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()> {
+        self.inner.read_sector(sector, buf)
+    }
+
+    fn write_sector(&mut self, sector: u64, buf: &[u8; SECTOR_SIZE]) -> io::Result<()> {
+        match self.writes_left {
+            Some(0) => return Err(io::Error::other("injected failure")),
+            Some(ref mut left) => *left -= 1,
+            None => ()
+        }
+        self.writes += 1;
+        self.inner.write_sector(sector, buf)
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.inner.sector_count()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn set_sector_count(&mut self, sectors: u64) -> io::Result<()> {
+        self.inner.set_sector_count(sectors)
+    }
+}
+
+// every directory (with a trailing '/') and file under `dir` with its data
+pub fn snapshot<D: BlockDevice>(test_fs: &mut MicroFS<D>, dir: &str, files: &mut Vec<(String, Vec<u8>)>) {
+    for entry in test_fs.list(dir).unwrap() {
+        if entry.is_dir {
+            files.push((entry.path.clone() + "/", Vec::new()));
+            snapshot(test_fs, &entry.path, files);
+        } else {
+            let mut data = Vec::new();
+            test_fs.extract(&entry.path, &mut data).unwrap();
+            files.push((entry.path, data));
+        }
+    }
+}
+
+// the image in `raw_image`, which must check out clean
+pub fn open_clean(raw_image: Vec<u8>) -> MicroFS<MemDevice> {
+    let mut test_fs = MicroFS::open_device(MemDevice::from_vec(raw_image)).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    test_fs
+}
+
+pub fn state(raw_image: Vec<u8>) -> Vec<(String, Vec<u8>)> {
+    let mut files = Vec::new();
+    snapshot(&mut open_clean(raw_image), "/", &mut files);
+    files
+}
@@ -0,0 +1,87 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+
+mod common;
+use common::{FailingDevice, state};
+
+// an image where /big, the table of /d and the files below it are scattered
+fn fragmented(name: &str) -> Vec<u8> {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(100), SuperBlock::new("test", 1, 100 * 512)).unwrap();
+    fs::write(name, [1; 1000]).unwrap();
+    for i in 0..8 {
+        test_fs.add_as(name, &format!("/f{}", i)).unwrap();
+    }
+    test_fs.mkdir("/d").unwrap();
+    test_fs.save().unwrap();
+    for i in &[1, 3, 5] {
+        test_fs.del(&format!("/f{}", i)).unwrap();
+    }
+    test_fs.save().unwrap();
+    fs::write(name, [2; 5000]).unwrap();
+    test_fs.add_as(name, "/big").unwrap();
+    for i in 0..20 {
+        fs::write(name, vec![i as u8; 10 + 100 * i]).unwrap();
+        test_fs.add_as(name, &format!("/d/g{}", i)).unwrap();
+    }
+    test_fs.save().unwrap();
+    fs::remove_file(name).expect("Failed removing the file");
+    test_fs.dev.into_inner()
+}
+
+// the blocks of every chain, in the order of the directory tree
+fn chains<D: BlockDevice>(test_fs: &MicroFS<D>, entries: &[Entry], blocks: &mut Vec<usize>) {
+    for entry in entries {
+        blocks.extend(test_fs.get_blocks(entry).unwrap());
+        chains(test_fs, &entry.children, blocks);
+    }
+}
+
+#[test]
+fn defrag() {
+    let base = fragmented("defrag_test.bin");
+    let files = state(base.clone());
+    let mut test_fs = MicroFS::open_device(MemDevice::from_vec(base)).unwrap();
+    let report = test_fs.defrag().unwrap();
+    assert_eq!(report.before.fragments.iter().find(|f| f.0 == "/big").unwrap().1, 4);
+    assert_eq!(report.before.fragments.iter().find(|f| f.0 == "/d/").unwrap().1, 2);
+    assert!(report.moved_blocks > 0);
+    assert_eq!(report.after.fragmented(), 0);
+    assert_eq!(report.after.fragments.len(), report.before.fragments.len());
+    assert_eq!(report.after.free_blocks, report.before.free_blocks);
+    assert_eq!(report.after.largest_free_run, report.after.free_blocks);
+
+    let mut blocks = Vec::new();
+    let entries = test_fs.entries.clone();
+    chains(&test_fs, &entries, &mut blocks);
+    let data_start = test_fs.sb.data_start();
+    assert_eq!(blocks, (data_start..data_start + blocks.len()).collect::<Vec<_>>());
+    assert_eq!(state(test_fs.dev.into_inner()), files);
+
+    // a second pass has nothing left to do
+    let mut test_fs = MicroFS::open_device(MemDevice::from_vec(fragmented("defrag_again_test.bin"))).unwrap();
+    test_fs.defrag().unwrap();
+    assert_eq!(test_fs.defrag().unwrap().moved_blocks, 0);
+}
+
+#[test]
+fn interrupted_defrag() {
+    let base = fragmented("defrag_interrupted_test.bin");
+    let files = state(base.clone());
+    let dev = FailingDevice::new(base.clone(), None);
+    let mut test_fs = MicroFS::open_device(dev).unwrap();
+    test_fs.defrag().unwrap();
+    let total = test_fs.dev.writes;
+
+    // whenever it stops, the image is consistent and holds the same files
+    for limit in 0..total {
+        let dev = FailingDevice::new(base.clone(), Some(limit));
+        let mut test_fs = MicroFS::open_device(dev).unwrap();
+        match test_fs.defrag() {
+            Err(MicroFsError::Io(_)) => (),
+            res => panic!("unexpected result {:?} at write {}", res, limit)
+        }
+        assert!(state(test_fs.dev.inner.into_inner()) == files, "files changed after failing at write {}", limit);
+    }
+}
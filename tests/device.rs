@@ -0,0 +1,58 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+
+#[test]
+fn unaligned_bytes() {
+    let mut dev = MemDevice::new(4);
+    dev.write_bytes(500, &[0xaa; 600]).unwrap();
+    let mut raw = [0; 700];
+    dev.read_bytes(450, &mut raw).unwrap();
+    assert_eq!(&raw[..50], &[0; 50][..]);
+    assert_eq!(&raw[50..650], &[0xaa; 600][..]);
+    assert_eq!(&raw[650..], &[0; 50][..]);
+    assert!(dev.read_bytes(4 * SECTOR_SIZE as u64 - 1, &mut raw).is_err());
+}
+
+#[test]
+fn memory_image() {
+    let dev = MemDevice::new(195);
+    let mut test_fs = MicroFS::format_device(dev, SuperBlock::new("test", 1, 100000)).unwrap();
+    test_fs.add("tests/test2.txt").unwrap();
+    test_fs.save().unwrap();
+    let raw_image = test_fs.dev.into_inner();
+    
+    // the same bytes as a file-backed image
+    let mut file_fs = MicroFS::format("device_memory_test.img", "test", 1, 100000).unwrap();
+    file_fs.add("tests/test2.txt").unwrap();
+    file_fs.save().unwrap();
+    assert_eq!(fs::read("device_memory_test.img").unwrap(), raw_image);
+    fs::remove_file("device_memory_test.img").expect("Failed removing the file");
+    
+    let mut test_fs = MicroFS::open_device(MemDevice::from_vec(raw_image)).unwrap();
+    let mut data = Vec::new();
+    test_fs.extract("test2.txt", &mut data).unwrap();
+    assert_eq!(data, fs::read("tests/test2.txt").unwrap());
+}
+
+#[test]
+fn slice_image() {
+    let mut raw_image = [0xee; 64 * SECTOR_SIZE];
+    {
+        let dev = SliceDevice::new(&mut raw_image);
+        let mut test_fs = MicroFS::format_device(dev, SuperBlock::new("test", 2, 32 * SECTOR_SIZE)).unwrap();
+        test_fs.add("tests/test1.txt").unwrap();
+        test_fs.save().unwrap();
+    }
+    let mut test_fs = MicroFS::open_device(SliceDevice::new(&mut raw_image)).unwrap();
+    assert_eq!(test_fs.entries.len(), 1);
+    let mut data = Vec::new();
+    test_fs.extract("test1.txt", &mut data).unwrap();
+    assert_eq!(data, fs::read("tests/test1.txt").unwrap());
+    
+    match MicroFS::format_device(MemDevice::new(10), SuperBlock::new("test", 1, 100000)) {
+        Err(MicroFsError::NoSpace { .. }) => (),
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+}
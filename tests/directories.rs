@@ -0,0 +1,119 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+
+fn names(entries: &[EntryInfo]) -> Vec<String> {
+    entries.iter().map(|e| if e.is_dir { format!("{}/", e.name) } else { e.name.clone() }).collect()
+}
+
+#[test]
+fn tree() {
+    fs::write("dir_tree_test.bin", [7; 600]).unwrap();
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    test_fs.mkdir("/fonts").unwrap();
+    test_fs.mkdir("/fonts/big").unwrap();
+    test_fs.add_as("dir_tree_test.bin", "/fonts/small.bin").unwrap();
+    test_fs.add_as("dir_tree_test.bin", "/fonts/big").unwrap();
+    test_fs.add("dir_tree_test.bin").unwrap();
+    test_fs.save().unwrap();
+    fs::remove_file("dir_tree_test.bin").expect("Failed removing the file");
+    
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert_eq!(names(&test_fs.list("/").unwrap()), ["fonts/", "dir_tree_test.bin"]);
+    assert_eq!(names(&test_fs.list("/fonts").unwrap()), ["big/", "small.bin"]);
+    assert_eq!(names(&test_fs.list("fonts/big/").unwrap()), ["dir_tree_test.bin"]);
+    assert_eq!(test_fs.find("/fonts").unwrap().size, 2 * ENTRY_SIZE as u32);
+    let mut data = Vec::new();
+    test_fs.extract("/fonts/small.bin", &mut data).unwrap();
+    assert_eq!(data, vec![7; 600]);
+    
+    test_fs.del("/fonts/small.bin").unwrap();
+    test_fs.save().unwrap();
+    let test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert_eq!(names(&test_fs.list("/fonts").unwrap()), ["big/"]);
+    assert_eq!(test_fs.find("/fonts").unwrap().size, ENTRY_SIZE as u32);
+}
+
+#[test]
+fn growing_directory() {
+    fs::write("dir_growing_test.bin", [1; 10]).unwrap();
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    test_fs.mkdir("/many").unwrap();
+    for i in 0..20 {
+        test_fs.add_as("dir_growing_test.bin", &format!("/many/f{}", i)).unwrap();
+    }
+    let dir = test_fs.find("/many").unwrap().clone();
+    assert_eq!(test_fs.get_blocks(&dir).unwrap().len(), 2);
+    test_fs.save().unwrap();
+    fs::remove_file("dir_growing_test.bin").expect("Failed removing the file");
+    
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert_eq!(test_fs.list("/many").unwrap().len(), 20);
+    for i in 0..10 {
+        test_fs.del(&format!("/many/f{}", i)).unwrap();
+    }
+    let dir = test_fs.find("/many").unwrap().clone();
+    assert_eq!(test_fs.get_blocks(&dir).unwrap().len(), 1);
+    
+    test_fs.rmdir("/many", true).unwrap();
+    test_fs.save().unwrap();
+    let test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert!(test_fs.entries.is_empty());
+    assert_eq!(test_fs.fat.as_bytes(), &[0xff; 195][..]);
+}
+
+#[test]
+fn errors() {
+    fs::write("dir_errors_test.bin", [1; 10]).unwrap();
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    test_fs.mkdir("/a").unwrap();
+    test_fs.add_as("dir_errors_test.bin", "/a/file").unwrap();
+    fs::remove_file("dir_errors_test.bin").expect("Failed removing the file");
+    
+    match test_fs.mkdir("/a") {
+        Err(MicroFsError::DuplicateName(name)) => assert_eq!(name, "/a"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.mkdir("/missing/b") {
+        Err(MicroFsError::NotFound(name)) => assert_eq!(name, "missing"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.mkdir("/a/file/b") {
+        Err(MicroFsError::NotADirectory(name)) => assert_eq!(name, "a/file"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    for &name in &["/", "/.", "/a/..", "/a\0b"] {
+        match test_fs.mkdir(name) {
+            Err(MicroFsError::InvalidName(invalid)) => assert_eq!(invalid, name),
+            res => panic!("unexpected result {:?}", res)
+        }
+    }
+    match test_fs.add_as("tests/test1.txt", "/a/..") {
+        Err(MicroFsError::InvalidName(name)) => assert_eq!(name, "/a/.."),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.mkdir("/abcdefghijklmnopqrstuvwxyz") {
+        Err(MicroFsError::NameTooLong(_)) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.del("/a") {
+        Err(MicroFsError::IsADirectory(name)) => assert_eq!(name, "/a"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.extract("/a", &mut Vec::new()) {
+        Err(MicroFsError::IsADirectory(name)) => assert_eq!(name, "/a"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.rmdir("/a/file", false) {
+        Err(MicroFsError::NotADirectory(name)) => assert_eq!(name, "/a/file"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.rmdir("/a", false) {
+        Err(MicroFsError::DirectoryNotEmpty(name)) => assert_eq!(name, "/a"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    test_fs.del("/a/file").unwrap();
+    test_fs.rmdir("/a", false).unwrap();
+    assert!(test_fs.list("/").unwrap().is_empty());
+}
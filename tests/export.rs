@@ -0,0 +1,168 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+use std::str;
+
+fn image(host: &str) -> MicroFS<MemDevice> {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    fs::write(host, [1; 700]).unwrap();
+    test_fs.add_as(host, "/a").unwrap();
+    test_fs.mkdir("/d").unwrap();
+    test_fs.add_as(host, "/d/b").unwrap();
+    test_fs.mkdir("/d/e").unwrap();
+    test_fs.save().unwrap();
+    fs::remove_file(host).expect("Failed removing the file");
+    test_fs
+}
+
+fn field(header: &[u8], start: usize, len: usize) -> &str {
+    str::from_utf8(&header[start..start + len]).unwrap().trim_end_matches('\0')
+}
+
+fn octal(header: &[u8], start: usize, len: usize) -> usize {
+    usize::from_str_radix(field(header, start, len).trim(), 8).unwrap()
+}
+
+// the members of a tar archive as (prefix, name, type, size, data)
+fn members(archive: &[u8]) -> Vec<(String, String, u8, usize, Vec<u8>)> {
+    let mut members = Vec::new();
+    let mut offset = 0;
+    while archive[offset..offset + 512].iter().any(|&b| b != 0) {
+        let header = &archive[offset..offset + 512];
+        let mut checksum: usize = header.iter().map(|&b| b as usize).sum();
+        checksum = checksum - header[148..156].iter().map(|&b| b as usize).sum::<usize>() + 8 * b' ' as usize;
+        assert_eq!(octal(header, 148, 7), checksum);
+        assert_eq!(field(header, 257, 6), "ustar");
+        let size = octal(header, 124, 12);
+        let data = archive[offset + 512..offset + 512 + size].to_vec();
+        members.push((field(header, 345, 155).to_string(), field(header, 0, 100).to_string(), header[156], size, data));
+        offset += 512 + size.div_ceil(512) * 512;
+    }
+    assert_eq!(archive.len(), offset + 1024);
+    assert!(archive[offset..].iter().all(|&b| b == 0));
+    members
+}
+
+#[test]
+fn directory() {
+    let mut test_fs = image("export_dir_test.bin");
+    assert_eq!(test_fs.export_dir("export_dir_test").unwrap(), 2);
+    assert_eq!(fs::read("export_dir_test/a").unwrap(), vec![1; 700]);
+    assert_eq!(fs::read("export_dir_test/d/b").unwrap(), vec![1; 700]);
+    assert!(fs::metadata("export_dir_test/d/e").unwrap().is_dir());
+    fs::remove_dir_all("export_dir_test").expect("Failed removing the directory");
+}
+
+#[test]
+fn tar() {
+    let mut test_fs = image("export_tar_test.bin");
+    let mut archive = Vec::new();
+    assert_eq!(test_fs.export_tar(&mut archive).unwrap(), 2);
+    let members = members(&archive);
+    let names: Vec<(&str, u8, usize)> = members.iter().map(|m| (&m.1[..], m.2, m.3)).collect();
+    assert_eq!(names, vec![("a", b'0', 700), ("d/", b'5', 0), ("d/b", b'0', 700), ("d/e/", b'5', 0)]);
+    assert_eq!(members[0].4, vec![1; 700]);
+}
+
+#[test]
+fn unsaved() {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    fs::write("export_unsaved_test.bin", [2; 700]).unwrap();
+    test_fs.add_as("export_unsaved_test.bin", "/a").unwrap();
+    fs::remove_file("export_unsaved_test.bin").expect("Failed removing the file");
+    let mut archive = Vec::new();
+    test_fs.export_tar(&mut archive).unwrap();
+    assert_eq!(members(&archive)[0].4, vec![2; 700]);
+}
+
+#[test]
+fn long_paths() {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    let name = "abcdefghijklmnopqrstuvwxy";
+    let mut path = String::new();
+    for _i in 0..5 {
+        path = format!("{}/{}", path, name);
+        test_fs.mkdir(&path).unwrap();
+    }
+    let mut archive = Vec::new();
+    test_fs.export_tar(&mut archive).unwrap();
+    let split = members(&archive);
+    // the last directory has a path of 5 * 26 bytes
+    assert_eq!(split[4].0, [name; 4].join("/"));
+    assert_eq!(split[4].1, format!("{}/", name));
+
+    // 6 directories at most fit in the prefix and 3 in the name, the path of the
+    // 10th goes in a PAX header
+    for _i in 0..5 {
+        path = format!("{}/{}", path, name);
+        test_fs.mkdir(&path).unwrap();
+    }
+    let mut archive = Vec::new();
+    test_fs.export_tar(&mut archive).unwrap();
+    let deep = members(&archive);
+    assert_eq!(deep.len(), 11);
+    assert_eq!((&deep[9].1[..], deep[9].2), ("PaxHeader", b'x'));
+    let record = format!("{}/\n", [name; 10].join("/"));
+    assert_eq!(String::from_utf8(deep[9].4.clone()).unwrap(), format!("{} path={}", record.len() + 9, record));
+    assert_eq!(deep[10].1, format!("{}/", [name; 10].join("/"))[..100]);
+    assert_eq!(deep[10].2, b'5');
+}
+
+#[test]
+fn long_names() {
+    let mut sb = SuperBlock::new("test", 1, 100000);
+    sb.version |= VERSION_LONG_NAMES;
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), sb).unwrap();
+    let name = "é".repeat(100);
+    fs::write("export_long_names_test.bin", [3; 10]).unwrap();
+    test_fs.add_as("export_long_names_test.bin", &format!("/{}", name)).unwrap();
+    fs::remove_file("export_long_names_test.bin").expect("Failed removing the file");
+    let mut archive = Vec::new();
+    test_fs.export_tar(&mut archive).unwrap();
+    let members = members(&archive);
+    assert_eq!(members.len(), 2);
+    assert_eq!(String::from_utf8(members[0].4.clone()).unwrap(), format!("210 path={}\n", name));
+    // the name field is cut between two characters
+    assert_eq!(members[1].1, "é".repeat(50));
+    assert_eq!(members[1].4, vec![3; 10]);
+}
+
+#[test]
+fn crafted_names() {
+    for &name in &["..", "../x", "d/../../x"] {
+        let mut test_fs = image("export_crafted_test.bin");
+        test_fs.find_mut("/a").unwrap().name = name.as_bytes().to_vec();
+        match test_fs.export_dir("export_crafted_test/out") {
+            Err(MicroFsError::InvalidName(path)) => assert_eq!(path, format!("/{}", name)),
+            res => panic!("unexpected result {:?}", res)
+        }
+        match test_fs.export_tar(&mut Vec::new()) {
+            Err(MicroFsError::InvalidName(path)) => assert_eq!(path, format!("/{}", name)),
+            res => panic!("unexpected result {:?}", res)
+        }
+        assert!(fs::metadata("export_crafted_test/x").is_err());
+        assert!(fs::read_dir("export_crafted_test/out").unwrap().next().is_none());
+    }
+    fs::remove_dir_all("export_crafted_test").expect("Failed removing the directory");
+}
+
+#[test]
+fn times_and_modes() {
+    let mut sb = SuperBlock::new("test", 1, 100000);
+    sb.version |= VERSION_ATTRIBUTES;
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), sb).unwrap();
+    test_fs.touch("/a", Some(1_000_000_000)).unwrap();
+    test_fs.set_attributes("/a", ATTR_READ_ONLY | ATTR_EXECUTABLE).unwrap();
+    test_fs.touch("/b", Some(42)).unwrap();
+    let mut archive = Vec::new();
+    test_fs.export_tar(&mut archive).unwrap();
+    assert_eq!((octal(&archive, 100, 8), octal(&archive, 136, 12)), (0o555, 1_000_000_000));
+    assert_eq!((octal(&archive[512..], 100, 8), octal(&archive[512..], 136, 12)), (0o644, 42));
+
+    // images without times keep the defaults
+    let mut test_fs = image("export_times_test.bin");
+    let mut archive = Vec::new();
+    test_fs.export_tar(&mut archive).unwrap();
+    assert_eq!((octal(&archive, 100, 8), octal(&archive, 136, 12)), (0o644, 0));
+}
@@ -0,0 +1,88 @@
+extern crate micro_fs;
+use micro_fs::*;
+use micro_fs::utils::bytes_to_str;
+
+use std::fs;
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn default_version() {
+    assert_eq!(SuperBlock::new("test", 1, 100000).version, VERSION_FAT8);
+    assert_eq!(SuperBlock::new("test", 1, 1000000).version, VERSION_FAT16);
+    assert_eq!(SuperBlock::new("test", 1, 40000000).version, VERSION_FAT32);
+
+    let sb = SuperBlock::new("test", 1, 1000000);
+    assert_eq!(sb.fat_width(), 2);
+    assert_eq!(sb.fat_size, 1953 * 2);
+    assert_eq!(sb.blocks(), 1953);
+    assert_eq!(sb.root_entry, 9);
+}
+
+#[test]
+fn fat_entries() {
+    for &width in &[1, 2, 4] {
+        let mut fat = Fat::new(width, 16);
+        assert_eq!(fat.len(), 16);
+        assert_eq!(fat.get(3), FatEntry::Free);
+        fat.set(3, FatEntry::Next(7));
+        fat.set(7, FatEntry::End);
+        assert_eq!(fat.get(3), FatEntry::Next(7));
+        assert_eq!(fat.get(7), FatEntry::End);
+        assert_eq!(fat.as_bytes().len(), 16 * width);
+    }
+    let mut fat = Fat::new(2, 1000);
+    fat.set(999, FatEntry::Next(300));
+    assert_eq!(fat.get(999), FatEntry::Next(300));
+    assert_eq!(Fat::new(1, 1).max_block(), 0xfe);
+}
+
+fn round_trip(image: &str, host: &str, size: usize, version: u16) {
+    let data = pattern(200000);
+    fs::write(host, &data).unwrap();
+
+    let mut test_fs = MicroFS::format_with(image, SuperBlock::with_version("test", 1, size, version)).unwrap();
+    test_fs.add(host).unwrap();
+    test_fs.save().unwrap();
+
+    let mut test_fs = MicroFS::open(image).unwrap();
+    assert_eq!(test_fs.sb.version, version);
+    let blocks = test_fs.get_blocks(&test_fs.entries[0].clone()).unwrap();
+    assert_eq!(blocks.len(), 391);
+    assert!(blocks[blocks.len() - 1] > 0xff);
+    let mut extracted = Vec::new();
+    let name = bytes_to_str(&test_fs.entries[0].name).to_string();
+    test_fs.extract(&name, &mut extracted).unwrap();
+    assert_eq!(extracted, data);
+
+    fs::remove_file(image).expect("Failed removing the file");
+    fs::remove_file(host).expect("Failed removing the file");
+}
+
+#[test]
+fn fat16_chain() {
+    round_trip("fat16_test.img", "fat16_test.bin", 1000000, VERSION_FAT16);
+}
+
+#[test]
+fn fat32_chain() {
+    round_trip("fat32_test.img", "fat32_test.bin", 1000000, VERSION_FAT32);
+}
+
+#[test]
+fn fat8_limit() {
+    // a version 1 image larger than 255 blocks only uses the blocks its FAT can address
+    let mut test_fs = MicroFS::format_with("fat8_test.img", SuperBlock::with_version("test", 1, 1000000, VERSION_FAT8)).unwrap();
+    fs::write("fat8_test.bin", pattern(200000)).unwrap();
+    match test_fs.add("fat8_test.bin") {
+        Err(MicroFsError::NoSpace { needed, available }) => {
+            assert_eq!(needed, 391);
+            assert_eq!(available, 0xff - 6);
+        },
+        res => panic!("unexpected result {:?}", res)
+    }
+    fs::remove_file("fat8_test.img").expect("Failed removing the file");
+    fs::remove_file("fat8_test.bin").expect("Failed removing the file");
+}
@@ -0,0 +1,155 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+fn image(host: &str, size: usize) -> MicroFS<MemDevice> {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    let data: Vec<u8> = (0..size).map(|i| i as u8).collect();
+    fs::write(host, &data).unwrap();
+    test_fs.add_as(host, "/f").unwrap();
+    fs::remove_file(host).expect("Failed removing the file");
+    test_fs
+}
+
+#[test]
+fn read_and_seek() {
+    let mut test_fs = image("file_read_test.bin", 3000);
+    let mut file = test_fs.open_file("/f", OpenMode::Read).unwrap();
+    assert_eq!(file.len(), 3000);
+    let mut record = [0; 100];
+    file.seek(SeekFrom::Start(1000)).unwrap();
+    file.read_exact(&mut record).unwrap();
+    assert_eq!(record[0], (1000 % 256) as u8);
+    assert_eq!(record[99], (1099 % 256) as u8);
+    assert_eq!(file.seek(SeekFrom::End(-10)).unwrap(), 2990);
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail.len(), 10);
+    assert_eq!(tail[9], (2999 % 256) as u8);
+    assert!(file.seek(SeekFrom::Current(-3001)).is_err());
+    assert!(file.write(&[1]).is_err());
+}
+
+#[test]
+fn write_in_place_and_past_the_end() {
+    let mut test_fs = image("file_write_test.bin", 600);
+    let start = test_fs.entries[0].start;
+    {
+        let mut file = test_fs.open_file("/f", OpenMode::Write).unwrap();
+        file.seek(SeekFrom::Start(510)).unwrap();
+        file.write_all(&[0xaa; 4]).unwrap();
+        file.seek(SeekFrom::Start(2000)).unwrap();
+        file.write_all(&[0xbb; 100]).unwrap();
+        assert_eq!(file.len(), 2100);
+    }
+    assert_eq!(test_fs.entries[0].start, start);
+    assert_eq!(test_fs.entries[0].size, 2100);
+    assert_eq!(test_fs.get_blocks(&test_fs.entries[0].clone()).unwrap().len(), 5);
+    assert!(test_fs.pending.is_empty());
+    test_fs.save().unwrap();
+
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    let mut data = Vec::new();
+    test_fs.extract("/f", &mut data).unwrap();
+    assert_eq!(data.len(), 2100);
+    assert_eq!(data[509], (509 % 256) as u8);
+    assert_eq!(&data[510..514], &[0xaa; 4]);
+    assert_eq!(data[514], (514 % 256) as u8);
+    assert!(data[600..2000].iter().all(|&b| b == 0));
+    assert!(data[2000..].iter().all(|&b| b == 0xbb));
+}
+
+#[test]
+fn create() {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    test_fs.mkdir("/d").unwrap();
+    match test_fs.open_file("/d/log", OpenMode::Write) {
+        Err(MicroFsError::NotFound(name)) => assert_eq!(name, "/d/log"),
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+    match test_fs.open_file("/d", OpenMode::Read) {
+        Err(MicroFsError::IsADirectory(name)) => assert_eq!(name, "/d"),
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+    {
+        let mut file = test_fs.open_file("/d/log", OpenMode::Create).unwrap();
+        for i in 0..10 {
+            writeln!(file, "record {}", i).unwrap();
+        }
+        file.flush().unwrap();
+    }
+    test_fs.save().unwrap();
+
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    let mut text = String::new();
+    test_fs.open_file("/d/log", OpenMode::Read).unwrap().read_to_string(&mut text).unwrap();
+    assert_eq!(text.lines().count(), 10);
+    assert_eq!(text.lines().last(), Some("record 9"));
+}
+
+#[test]
+fn no_space() {
+    // 27 blocks, 7 of them for the journal
+    let mut test_fs = MicroFS::format_device(MemDevice::new(27), SuperBlock::new("test", 1, 14000)).unwrap();
+    test_fs.add_as("tests/test2.txt", "/f").unwrap();
+    let mut file = test_fs.open_file("/f", OpenMode::Write).unwrap();
+    file.seek(SeekFrom::Start(20 * SECTOR_SIZE as u64)).unwrap();
+    assert!(file.write(&[1]).is_err());
+}
+
+#[test]
+fn set_len() {
+    let mut test_fs = image("file_len_test.bin", 2000);
+    {
+        let mut file = test_fs.open_file("/f", OpenMode::Write).unwrap();
+        file.set_len(700).unwrap();
+        file.set_len(1200).unwrap();
+        assert_eq!(file.stream_position().unwrap(), 0);
+    }
+    assert_eq!(test_fs.entries[0].size, 1200);
+    assert_eq!(test_fs.get_blocks(&test_fs.entries[0].clone()).unwrap().len(), 3);
+    test_fs.save().unwrap();
+
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    let mut data = Vec::new();
+    test_fs.extract("/f", &mut data).unwrap();
+    assert_eq!(data[699], (699 % 256) as u8);
+    assert!(data[700..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn detach() {
+    let mut test_fs = image("file_detach_test.bin", 600);
+    let file = test_fs.open_file("/f", OpenMode::Write).unwrap().detach().unwrap();
+    let file = {
+        let mut handle = test_fs.attach(file).unwrap();
+        handle.seek(SeekFrom::End(0)).unwrap();
+        handle.write_all(&[0xaa; 1000]).unwrap();
+        handle.detach().unwrap()
+    };
+    assert_eq!(test_fs.entries[0].size, 1600);
+    {
+        // the file changed in between, the handle follows it
+        let mut other = test_fs.open_file("/f", OpenMode::Write).unwrap();
+        other.set_len(100).unwrap();
+    }
+    {
+        let mut handle = test_fs.attach(file).unwrap();
+        assert_eq!(handle.stream_position().unwrap(), 1600);
+        handle.write_all(&[0xbb; 10]).unwrap();
+    }
+    assert_eq!(test_fs.entries[0].size, 1610);
+    test_fs.save().unwrap();
+
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    let mut data = Vec::new();
+    test_fs.extract("/f", &mut data).unwrap();
+    assert_eq!(data[99], 99);
+    assert!(data[100..1600].iter().all(|&b| b == 0));
+    assert!(data[1600..].iter().all(|&b| b == 0xbb));
+}
@@ -0,0 +1,96 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+use std::path::Path;
+
+// a host tree of 8 blocks of data with a block size of 1
+fn tree(dir: &str) {
+    fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+    fs::write(format!("{}/b.bin", dir), [2; 10]).unwrap();
+    fs::write(format!("{}/a.bin", dir), [1; 2000]).unwrap();
+    fs::write(format!("{}/sub/c.bin", dir), [3; 600]).unwrap();
+}
+
+fn free_blocks<D: BlockDevice>(test_fs: &MicroFS<D>) -> usize {
+    (test_fs.sb.data_start()..test_fs.sb.journal_start()).filter(|&block| test_fs.fat.get(block) == FatEntry::Free).count()
+}
+
+#[test]
+fn minimum_size() {
+    tree("from_dir_min_test");
+    let size = MicroFS::min_size("from_dir_min_test", 1).unwrap();
+    let test_fs = MicroFS::from_dir("from_dir_min_test.img", "from_dir_min_test", "test", 1, &BuildOptions::default()).unwrap();
+    assert_eq!(test_fs.sb.blocks() * SECTOR_SIZE, size);
+    assert_eq!(fs::metadata("from_dir_min_test.img").unwrap().len() as usize, size);
+    assert_eq!(free_blocks(&test_fs), 0);
+
+    let mut test_fs = MicroFS::open("from_dir_min_test.img").unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    let mut data = Vec::new();
+    test_fs.extract("/sub/c.bin", &mut data).unwrap();
+    assert_eq!(data, vec![3; 600]);
+    assert_eq!(test_fs.list("/").unwrap().len(), 3);
+
+    let options = BuildOptions { size: Some(size - SECTOR_SIZE), ..BuildOptions::default() };
+    match MicroFS::from_dir("from_dir_min_test.img", "from_dir_min_test", "test", 1, &options) {
+        Err(MicroFsError::NoSpace { needed, available }) => {
+            assert_eq!(needed, 8);
+            assert_eq!(available, 7);
+        },
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+
+    fs::remove_dir_all("from_dir_min_test").expect("Failed removing the directory");
+    fs::remove_file("from_dir_min_test.img").expect("Failed removing the file");
+}
+
+#[test]
+fn size_and_padding() {
+    tree("from_dir_padding_test");
+    let options = BuildOptions { padding: 5000, ..BuildOptions::default() };
+    let test_fs = MicroFS::from_dir("from_dir_padding_test.img", "from_dir_padding_test", "test", 2, &options).unwrap();
+    // 5000 bytes are 5 blocks of 1024 bytes
+    assert!(free_blocks(&test_fs) >= 5);
+
+    let options = BuildOptions { size: Some(100000), ..BuildOptions::default() };
+    let test_fs = MicroFS::from_dir("from_dir_padding_test.img", "from_dir_padding_test", "test", 2, &options).unwrap();
+    assert_eq!(test_fs.sb.blocks(), 97);
+    // the tree takes 5 blocks of 1024 bytes
+    assert_eq!(free_blocks(&test_fs), test_fs.sb.journal_start() - test_fs.sb.data_start() - 5);
+
+    fs::remove_dir_all("from_dir_padding_test").expect("Failed removing the directory");
+    fs::remove_file("from_dir_padding_test.img").expect("Failed removing the file");
+}
+
+#[test]
+fn sorted() {
+    tree("from_dir_sorted_test");
+    let options = BuildOptions { sorted: true, ..BuildOptions::default() };
+    MicroFS::from_dir("from_dir_sorted_test1.img", "from_dir_sorted_test", "test", 1, &options).unwrap();
+    MicroFS::from_dir("from_dir_sorted_test2.img", "from_dir_sorted_test", "test", 1, &options).unwrap();
+    assert!(fs::read("from_dir_sorted_test1.img").unwrap() == fs::read("from_dir_sorted_test2.img").unwrap());
+    let test_fs = MicroFS::open("from_dir_sorted_test1.img").unwrap();
+    let names: Vec<String> = test_fs.list("/").unwrap().into_iter().map(|e| e.name).collect();
+    assert_eq!(names, vec!["a.bin", "b.bin", "sub"]);
+
+    fs::remove_dir_all("from_dir_sorted_test").expect("Failed removing the directory");
+    fs::remove_file("from_dir_sorted_test1.img").expect("Failed removing the file");
+    fs::remove_file("from_dir_sorted_test2.img").expect("Failed removing the file");
+}
+
+#[test]
+fn errors() {
+    tree("from_dir_errors_test");
+    fs::write("from_dir_errors_test/sub/abcdefghijklmnopqrstuvwxyz0", [0; 1]).unwrap();
+    match MicroFS::from_dir("from_dir_errors_test.img", "from_dir_errors_test", "test", 1, &BuildOptions::default()) {
+        Err(MicroFsError::NameTooLong(name)) => assert_eq!(name, "from_dir_errors_test/sub/abcdefghijklmnopqrstuvwxyz0"),
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+    assert!(!Path::new("from_dir_errors_test.img").exists());
+    match MicroFS::from_dir("from_dir_errors_test.img", "from_dir_missing_test", "test", 1, &BuildOptions::default()) {
+        Err(MicroFsError::NotFound(name)) => assert_eq!(name, "from_dir_missing_test"),
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+    fs::remove_dir_all("from_dir_errors_test").expect("Failed removing the directory");
+}
@@ -0,0 +1,46 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+
+#[test]
+fn list() {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    fs::write("info_list_test.bin", [1; 1500]).unwrap();
+    test_fs.add_as("info_list_test.bin", "/a").unwrap();
+    test_fs.mkdir("/d").unwrap();
+    test_fs.add_as("info_list_test.bin", "/d/b").unwrap();
+    fs::remove_file("info_list_test.bin").expect("Failed removing the file");
+
+    let entries = test_fs.list("/").unwrap();
+    assert_eq!(entries, vec![
+        EntryInfo { name: "a".to_string(), path: "/a".to_string(), is_dir: false, size: 1500, children: 0, start: 3, blocks: 3, allocated: 1536,
+                    created: 0, modified: 0, attributes: 0 },
+        EntryInfo { name: "d".to_string(), path: "/d".to_string(), is_dir: true, size: 32, children: 1, start: 6, blocks: 1, allocated: 512,
+                    created: 0, modified: 0, attributes: 0 }
+    ]);
+    let entries = test_fs.list("d/b").unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].path, "/d/b");
+    assert_eq!(test_fs.list("/d/").unwrap(), entries);
+}
+
+#[test]
+fn info() {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    fs::write("info_blocks_test.bin", [1; 1500]).unwrap();
+    test_fs.add("info_blocks_test.bin").unwrap();
+    fs::remove_file("info_blocks_test.bin").expect("Failed removing the file");
+
+    let info = test_fs.info();
+    assert_eq!(info.label, "test");
+    assert_eq!(info.version, VERSION_FAT8);
+    assert_eq!(info.fat_bits, 8);
+    assert_eq!(info.block_size, 512);
+    assert_eq!(info.root_entries, 16);
+    assert_eq!(info.total_blocks, 195);
+    // the super block, the FAT and the root directory take a block each, the journal 7
+    assert_eq!(info.data_blocks, 185);
+    assert_eq!(info.used_blocks, 3);
+    assert_eq!(info.free_blocks, 182);
+}
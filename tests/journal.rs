@@ -0,0 +1,185 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+
+mod common;
+use common::{FailingDevice, state};
+
+fn changes<D: BlockDevice>(test_fs: &mut MicroFS<D>) {
+    test_fs.del("/a").unwrap();
+    test_fs.add_as("journal_c_test.bin", "/c").unwrap();
+    test_fs.add_as("journal_b_test.bin", "/d/e").unwrap();
+    test_fs.mkdir("/x").unwrap();
+    // the saved data of /b stays whole until the new one is committed
+    test_fs.replace("journal_c_test.bin", "/b").unwrap();
+}
+
+#[test]
+fn interrupted_save() {
+    fs::write("journal_a_test.bin", [1; 2000]).unwrap();
+    fs::write("journal_b_test.bin", [2; 600]).unwrap();
+    fs::write("journal_c_test.bin", [3; 2500]).unwrap();
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    test_fs.add_as("journal_a_test.bin", "/a").unwrap();
+    test_fs.add_as("journal_b_test.bin", "/b").unwrap();
+    test_fs.mkdir("/d").unwrap();
+    test_fs.save().unwrap();
+    let base = test_fs.dev.into_inner();
+
+    // a save that goes through, to count its writes and know the new state
+    let dev = FailingDevice::new(base.clone(), None);
+    let mut test_fs = MicroFS::open_device(dev).unwrap();
+    changes(&mut test_fs);
+    test_fs.save().unwrap();
+    let total = test_fs.dev.writes;
+    let old = state(base.clone());
+    let new = state(test_fs.dev.inner.into_inner());
+    assert!(old != new);
+
+    let mut replayed = 0;
+    for limit in 0..total {
+        let dev = FailingDevice::new(base.clone(), None);
+        let mut test_fs = MicroFS::open_device(dev).unwrap();
+        changes(&mut test_fs);
+        test_fs.dev.writes_left = Some(limit);
+        match test_fs.save() {
+            Err(MicroFsError::Io(_)) => (),
+            res => panic!("unexpected result {:?} at write {}", res, limit)
+        }
+        let after = state(test_fs.dev.inner.into_inner());
+        if after == new {
+            replayed += 1;
+        } else {
+            assert!(after == old, "mixed state after failing at write {}", limit);
+        }
+    }
+    assert!(replayed > 0);
+    assert!(replayed < total);
+
+    for name in &["journal_a_test.bin", "journal_b_test.bin", "journal_c_test.bin"] {
+        fs::remove_file(name).expect("Failed removing the file");
+    }
+}
+
+#[test]
+fn grown_journal() {
+    // the root directory alone takes more sectors than the journal
+    let mut sb = SuperBlock::new("test", 1, 100000);
+    sb.root_blocks = 4;
+    sb.journal_blocks = 3;
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), sb).unwrap();
+    fs::write("journal_grown_test.bin", [1; 10]).unwrap();
+    for i in 0..64 {
+        test_fs.add_as("journal_grown_test.bin", &format!("/f{}", i)).unwrap();
+    }
+    fs::remove_file("journal_grown_test.bin").expect("Failed removing the file");
+    test_fs.save().unwrap();
+    assert_eq!(test_fs.sb.journal_blocks, 7);
+    let test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert_eq!(test_fs.sb.journal_blocks, 7);
+    let files = state(test_fs.dev.into_inner());
+    assert_eq!(files.len(), 64);
+    assert!(files.iter().all(|(_, data)| data[..] == [1; 10][..]));
+
+    // large directory tables with the default journal
+    let mut test_fs = MicroFS::format_device(MemDevice::new(585), SuperBlock::new("test", 1, 300000)).unwrap();
+    test_fs.mkdir("/d").unwrap();
+    fs::write("journal_grown_dir_test.bin", [2; 10]).unwrap();
+    for i in 0..200 {
+        test_fs.add_as("journal_grown_dir_test.bin", &format!("/d/f{}", i)).unwrap();
+    }
+    fs::remove_file("journal_grown_dir_test.bin").expect("Failed removing the file");
+    test_fs.save().unwrap();
+    test_fs.del("/d/f1").unwrap();
+    test_fs.save().unwrap();
+    assert_eq!(state(test_fs.dev.into_inner()).len(), 200);
+}
+
+#[test]
+fn interrupted_growth() {
+    // a save changing more directory tables than the default journal holds
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    fs::write("journal_growth_test.bin", [1; 10]).unwrap();
+    for i in 0..6 {
+        test_fs.mkdir(&format!("/d{}", i)).unwrap();
+        test_fs.add_as("journal_growth_test.bin", &format!("/d{}/a", i)).unwrap();
+        test_fs.save().unwrap();
+    }
+    let journal_blocks = test_fs.sb.journal_blocks;
+    let base = test_fs.dev.into_inner();
+    let changes = |test_fs: &mut MicroFS<FailingDevice>| {
+        for i in 0..6 {
+            test_fs.del(&format!("/d{}/a", i)).unwrap();
+            test_fs.add_as("journal_growth_test.bin", &format!("/d{}/b", i)).unwrap();
+        }
+    };
+
+    let mut test_fs = MicroFS::open_device(FailingDevice::new(base.clone(), None)).unwrap();
+    changes(&mut test_fs);
+    test_fs.save().unwrap();
+    assert!(test_fs.sb.journal_blocks > journal_blocks);
+    let total = test_fs.dev.writes;
+    let old = state(base.clone());
+    let new = state(test_fs.dev.inner.into_inner());
+
+    for limit in 0..total {
+        let mut test_fs = MicroFS::open_device(FailingDevice::new(base.clone(), None)).unwrap();
+        changes(&mut test_fs);
+        test_fs.dev.writes_left = Some(limit);
+        match test_fs.save() {
+            Err(MicroFsError::Io(_)) => (),
+            res => panic!("unexpected result {:?} at write {}", res, limit)
+        }
+        let after = state(test_fs.dev.inner.into_inner());
+        assert!(after == old || after == new, "mixed state after failing at write {}", limit);
+    }
+    fs::remove_file("journal_growth_test.bin").expect("Failed removing the file");
+}
+
+#[test]
+fn journal_full() {
+    // too few free blocks are left before the journal for it to grow
+    let mut sb = SuperBlock::new("test", 1, 100000);
+    sb.root_blocks = 4;
+    sb.journal_blocks = 3;
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), sb).unwrap();
+    fs::write("journal_full_test.bin", [1; 10]).unwrap();
+    for i in 0..64 {
+        test_fs.add_as("journal_full_test.bin", &format!("/f{}", i)).unwrap();
+    }
+    fs::write("journal_full_test.bin", vec![2; 120 * 512]).unwrap();
+    test_fs.replace("journal_full_test.bin", "/f0").unwrap();
+    fs::remove_file("journal_full_test.bin").expect("Failed removing the file");
+    let before = test_fs.dev.clone().into_inner();
+    match test_fs.save() {
+        Err(MicroFsError::JournalFull { needed, available }) => {
+            assert_eq!(needed, 7);
+            assert_eq!(available, 3);
+        },
+        res => panic!("unexpected result {:?}", res)
+    }
+    assert!(test_fs.dev.as_bytes() == &before[..]);
+    assert_eq!(test_fs.pending.len(), 64);
+}
+
+#[test]
+fn root_blocks() {
+    // the journal follows the size of the root directory
+    let mut sb = SuperBlock::new("test", 1, 100000);
+    let journal_blocks = sb.journal_blocks;
+    sb.set_root_blocks(8);
+    assert_eq!(sb.journal_blocks, journal_blocks + 7);
+}
+
+#[test]
+fn without_journal() {
+    let mut sb = SuperBlock::new("test", 1, 100000);
+    sb.journal_blocks = 0;
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), sb).unwrap();
+    fs::write("journal_none_test.bin", [5; 700]).unwrap();
+    test_fs.add("journal_none_test.bin").unwrap();
+    test_fs.save().unwrap();
+    fs::remove_file("journal_none_test.bin").expect("Failed removing the file");
+    assert_eq!(state(test_fs.dev.into_inner()), vec![("/journal_none_test.bin".to_string(), vec![5; 700])]);
+}
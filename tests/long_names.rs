@@ -0,0 +1,182 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+
+fn image(blocks: usize) -> MicroFS<MemDevice> {
+    let mut sb = SuperBlock::new("test", 1, blocks * 512);
+    sb.version |= VERSION_LONG_NAMES;
+    MicroFS::format_device(MemDevice::new(blocks), sb).unwrap()
+}
+
+fn reopen(test_fs: MicroFS<MemDevice>) -> MicroFS<MemDevice> {
+    let mut test_fs = MicroFS::open_device(MemDevice::from_vec(test_fs.dev.into_inner())).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    test_fs
+}
+
+fn names<D: BlockDevice>(test_fs: &MicroFS<D>, dir: &str) -> Vec<String> {
+    test_fs.list(dir).unwrap().into_iter().map(|e| e.name).collect()
+}
+
+#[test]
+fn round_trip() {
+    for &version in &[VERSION_FAT16, VERSION_FAT32, VERSION_FAT16 | VERSION_ATTRIBUTES] {
+        let mut sb = SuperBlock::with_version("test", 1, 200 * 512, version | VERSION_LONG_NAMES);
+        sb.root_blocks = 2;
+        let mut test_fs = MicroFS::format_device(MemDevice::new(200), sb).unwrap();
+        let long = "é".repeat(127) + "x";
+        assert_eq!(long.len(), LONG_NAME_SIZE);
+        // two names sharing the part kept in the entry itself
+        let shared = "a_name_sharing_its_first_bytes_";
+        let dir = "a directory with a name longer than the name field";
+        fs::write("long_names_round_trip_test.bin", [1; 700]).unwrap();
+        test_fs.add_as("long_names_round_trip_test.bin", &format!("/{}", long)).unwrap();
+        test_fs.add_as("long_names_round_trip_test.bin", &format!("/{}1", shared)).unwrap();
+        test_fs.add_as("long_names_round_trip_test.bin", "/short").unwrap();
+        test_fs.mkdir(&format!("/{}", dir)).unwrap();
+        test_fs.add_as("long_names_round_trip_test.bin", &format!("/{}/{}2", dir, shared)).unwrap();
+        fs::remove_file("long_names_round_trip_test.bin").expect("Failed removing the file");
+        test_fs.save().unwrap();
+
+        let mut test_fs = reopen(test_fs);
+        assert_eq!(names(&test_fs, "/"), vec![long.clone(), format!("{}1", shared), "short".to_string(), dir.to_string()]);
+        assert_eq!(names(&test_fs, dir), vec![format!("{}2", shared)]);
+        let mut data = Vec::new();
+        test_fs.extract(&format!("/{}/{}2", dir, shared), &mut data).unwrap();
+        assert_eq!(data, vec![1; 700]);
+
+        // short names keep the single entry form
+        let entry_size = test_fs.sb.entry_size();
+        assert_eq!(test_fs.find("/short").unwrap().size, 700);
+        assert_eq!(test_fs.find(&format!("/{}", dir)).unwrap().size as usize, 2 * entry_size);
+    }
+}
+
+#[test]
+fn rename() {
+    let mut test_fs = image(200);
+    test_fs.mkdir("/d").unwrap();
+    fs::write("long_names_rename_test.bin", [1; 10]).unwrap();
+    test_fs.add_as("long_names_rename_test.bin", "/d/f").unwrap();
+    fs::remove_file("long_names_rename_test.bin").expect("Failed removing the file");
+    assert_eq!(test_fs.find("/d").unwrap().size, 32);
+
+    // the table of /d grows with the name and shrinks back
+    let long = "f".repeat(100);
+    test_fs.rename("/d/f", &format!("/d/{}", long)).unwrap();
+    assert_eq!(test_fs.find("/d").unwrap().size, 32 * 4);
+    test_fs.rename(&format!("/d/{}", long), &format!("/{}", long)).unwrap();
+    assert_eq!(test_fs.find("/d").unwrap().size, 0);
+    test_fs.save().unwrap();
+    let mut test_fs = reopen(test_fs);
+    assert_eq!(names(&test_fs, "/"), vec!["d".to_string(), long.clone()]);
+
+    test_fs.rename(&format!("/{}", long), "/g").unwrap();
+    test_fs.save().unwrap();
+    let test_fs = reopen(test_fs);
+    assert_eq!(names(&test_fs, "/"), vec!["d".to_string(), "g".to_string()]);
+}
+
+#[test]
+fn too_long() {
+    let mut test_fs = image(200);
+    let name = "n".repeat(LONG_NAME_SIZE + 1);
+    fs::write("long_names_too_long_test.bin", [1; 10]).unwrap();
+    match test_fs.add_as("long_names_too_long_test.bin", &format!("/{}", name)) {
+        Err(MicroFsError::NameTooLong(long)) => assert_eq!(long, name),
+        res => panic!("unexpected result {:?}", res)
+    }
+    // the trailing '/' of a directory counts
+    match test_fs.mkdir(&format!("/{}", &name[1..])) {
+        Err(MicroFsError::NameTooLong(_)) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+
+    // without long names, the name field is the limit
+    let mut test_fs = MicroFS::format_device(MemDevice::new(100), SuperBlock::new("test", 1, 100 * 512)).unwrap();
+    assert_eq!(test_fs.sb.max_name_size(), NAME_SIZE);
+    match test_fs.add_as("long_names_too_long_test.bin", "/abcdefghijklmnopqrstuvwxyz0") {
+        Err(MicroFsError::NameTooLong(long)) => assert_eq!(long, "abcdefghijklmnopqrstuvwxyz0"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    fs::remove_file("long_names_too_long_test.bin").expect("Failed removing the file");
+}
+
+#[test]
+fn root_full() {
+    // 16 entries of 32 bytes in the root directory
+    let mut test_fs = image(100);
+    fs::write("long_names_root_test.bin", [1; 10]).unwrap();
+    // 1 + 3 entries each
+    for i in 0..4 {
+        test_fs.add_as("long_names_root_test.bin", &format!("/{}{}", "r".repeat(100), i)).unwrap();
+    }
+    match test_fs.add_as("long_names_root_test.bin", "/x") {
+        Err(MicroFsError::DirectoryFull) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    let report = test_fs.check_fit(&["long_names_root_test.bin"], "/").unwrap();
+    assert_eq!(report.free_entries, Some(0));
+    assert!(!report.fits());
+    fs::remove_file("long_names_root_test.bin").expect("Failed removing the file");
+}
+
+#[test]
+fn wider_fat() {
+    // a 25 byte name is continued whatever the width of the FAT, a 32-bit FAT
+    // leaving less room in the entry does not change the tables
+    let mut test_fs = image(100);
+    let name = "a_name_of_25_bytes_xxxxxx";
+    fs::write("long_names_wider_test.bin", [1; 10]).unwrap();
+    test_fs.add_as("long_names_wider_test.bin", &format!("/{}", name)).unwrap();
+    fs::remove_file("long_names_wider_test.bin").expect("Failed removing the file");
+    test_fs.save().unwrap();
+    test_fs.resize(0x10000 * 512).unwrap();
+    assert_eq!(test_fs.sb.version, VERSION_FAT32 | VERSION_LONG_NAMES);
+    let test_fs = reopen(test_fs);
+    assert_eq!(names(&test_fs, "/"), vec![name.to_string()]);
+}
+
+#[test]
+fn from_dir() {
+    let long = "l".repeat(200);
+    fs::create_dir_all(format!("long_names_from_dir_test/{}", long)).unwrap();
+    fs::write(format!("long_names_from_dir_test/{}/{}", long, long), [1; 10]).unwrap();
+    match MicroFS::from_dir("long_names_from_dir_test.img", "long_names_from_dir_test", "test", 1, &BuildOptions::default()) {
+        Err(MicroFsError::NameTooLong(_)) => (),
+        res => panic!("unexpected result {:?}", res.map(|_| ()))
+    }
+    let options = BuildOptions { long_names: true, ..BuildOptions::default() };
+    let mut test_fs = MicroFS::from_dir("long_names_from_dir_test.img", "long_names_from_dir_test", "test", 1, &options).unwrap();
+    fs::remove_dir_all("long_names_from_dir_test").expect("Failed removing the directory");
+    assert!(test_fs.check().unwrap().is_clean());
+    let mut data = Vec::new();
+    test_fs.extract(&format!("/{}/{}", long, long), &mut data).unwrap();
+    assert_eq!(data, vec![1; 10]);
+    fs::remove_file("long_names_from_dir_test.img").expect("Failed removing the image");
+}
+
+#[test]
+fn marker_names() {
+    // a name starting with the marker would be read back as a continuation entry
+    let mut test_fs = image(100);
+    fs::write("long_names_marker_test.bin", [1; 10]).unwrap();
+    match test_fs.add_as("long_names_marker_test.bin", "/\u{1}\u{2}xyz") {
+        Err(MicroFsError::InvalidName(name)) => assert_eq!(name, "/\u{1}\u{2}xyz"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    test_fs.add_as("long_names_marker_test.bin", "/f").unwrap();
+    fs::remove_file("long_names_marker_test.bin").expect("Failed removing the file");
+    match test_fs.mkdir("/\u{1}d") {
+        Err(MicroFsError::InvalidName(_)) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.rename("/f", "/\u{1}f") {
+        Err(MicroFsError::InvalidName(_)) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    test_fs.save().unwrap();
+    let test_fs = reopen(test_fs);
+    assert_eq!(names(&test_fs, "/"), vec!["f".to_string()]);
+}
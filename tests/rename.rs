@@ -0,0 +1,93 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+
+fn image(host: &str) -> MicroFS<MemDevice> {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    fs::write(host, [7; 1500]).unwrap();
+    test_fs.add_as(host, "/a").unwrap();
+    test_fs.add_as(host, "/b").unwrap();
+    test_fs.mkdir("/d").unwrap();
+    test_fs.mkdir("/d/e").unwrap();
+    test_fs.save().unwrap();
+    fs::remove_file(host).expect("Failed removing the file");
+    test_fs
+}
+
+#[test]
+fn rename_in_place() {
+    let mut test_fs = image("rename_in_place_test.bin");
+    let fat = test_fs.fat.clone();
+    let start = test_fs.find("/a").unwrap().start;
+    test_fs.rename("/a", "/c").unwrap();
+    test_fs.rename("/d/e", "/d/f").unwrap();
+    assert_eq!(test_fs.fat, fat);
+    assert_eq!(test_fs.find("/c").unwrap().start, start);
+    assert!(test_fs.find("/a").is_err());
+    assert!(test_fs.find("/d/f").unwrap().is_dir());
+    test_fs.save().unwrap();
+
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    let mut data = Vec::new();
+    test_fs.extract("/c", &mut data).unwrap();
+    assert_eq!(data, vec![7; 1500]);
+    assert_eq!(test_fs.list("/d").unwrap()[0].name, "f");
+}
+
+#[test]
+fn move_between_directories() {
+    let mut test_fs = image("rename_move_test.bin");
+    let start = test_fs.find("/a").unwrap().start;
+    test_fs.rename("/a", "/d/e").unwrap();
+    test_fs.rename("/b", "/d/e/x").unwrap();
+    test_fs.rename("/d/e", "/").unwrap();
+    assert_eq!(test_fs.find("/e/a").unwrap().start, start);
+    assert_eq!(test_fs.list("/").unwrap().len(), 2);
+    assert!(test_fs.list("/d").unwrap().is_empty());
+    test_fs.save().unwrap();
+
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    let mut data = Vec::new();
+    test_fs.extract("/e/x", &mut data).unwrap();
+    assert_eq!(data, vec![7; 1500]);
+}
+
+#[test]
+fn errors() {
+    let mut test_fs = image("rename_errors_test.bin");
+    match test_fs.rename("/missing", "/c") {
+        Err(MicroFsError::NotFound(name)) => assert_eq!(name, "/missing"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.rename("/a", "/b") {
+        Err(MicroFsError::DuplicateName(name)) => assert_eq!(name, "/b"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    for &name in &["/.", "/..", "/d/..", "/x\0"] {
+        match test_fs.rename("/a", name) {
+            Err(MicroFsError::InvalidName(_)) => (),
+            res => panic!("unexpected result {:?}", res)
+        }
+    }
+    match test_fs.rename("/a", "/abcdefghijklmnopqrstuvwxyz0") {
+        Err(MicroFsError::NameTooLong(name)) => assert_eq!(name, "abcdefghijklmnopqrstuvwxyz0"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    // the trailing '/' of a directory counts against the limit
+    match test_fs.rename("/d", "/abcdefghijklmnopqrstuvwxyz") {
+        Err(MicroFsError::NameTooLong(name)) => assert_eq!(name, "abcdefghijklmnopqrstuvwxyz"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    test_fs.rename("/a", "/abcdefghijklmnopqrstuvwxyz").unwrap();
+    match test_fs.rename("/d", "/d/e") {
+        Err(MicroFsError::InvalidMove(name)) => assert_eq!(name, "/d/e/d"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.rename("/b", "/missing/b") {
+        Err(MicroFsError::NotFound(name)) => assert_eq!(name, "missing"),
+        res => panic!("unexpected result {:?}", res)
+    }
+}
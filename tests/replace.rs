@@ -0,0 +1,84 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+
+fn contents<D: BlockDevice>(test_fs: &mut MicroFS<D>, path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    test_fs.extract(path, &mut data).unwrap();
+    data
+}
+
+#[test]
+fn duplicate() {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    fs::write("replace_duplicate_test.bin", [1; 100]).unwrap();
+    test_fs.add_as("replace_duplicate_test.bin", "/x").unwrap();
+    test_fs.mkdir("/d").unwrap();
+    match test_fs.add_as("replace_duplicate_test.bin", "/x") {
+        Err(MicroFsError::DuplicateName(name)) => assert_eq!(name, "/x"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.replace("replace_duplicate_test.bin", "/d/") {
+        Ok(()) => assert_eq!(test_fs.list("/d").unwrap()[0].name, "replace_duplicate_test.bin"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    fs::remove_file("replace_duplicate_test.bin").expect("Failed removing the file");
+}
+
+#[test]
+fn shrink_and_grow() {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    fs::write("replace_a_test.bin", [1; 2000]).unwrap();
+    fs::write("replace_b_test.bin", [2; 600]).unwrap();
+    fs::write("replace_c_test.bin", [3; 3000]).unwrap();
+    test_fs.add_as("replace_a_test.bin", "/x").unwrap();
+    test_fs.add_as("replace_a_test.bin", "/y").unwrap();
+    test_fs.save().unwrap();
+    let old = test_fs.get_blocks(&test_fs.entries[0].clone()).unwrap();
+    assert_eq!(old.len(), 4);
+
+    // the new data goes to blocks the saved image does not use
+    test_fs.replace("replace_b_test.bin", "/x").unwrap();
+    assert_eq!(test_fs.entries.len(), 2);
+    assert_eq!(test_fs.entries[0].size, 600);
+    let blocks = test_fs.get_blocks(&test_fs.entries[0].clone()).unwrap();
+    assert_eq!(blocks.len(), 2);
+    assert!(blocks.iter().all(|block| !old.contains(block)));
+    assert!(old.iter().all(|&block| test_fs.fat.get(block) == FatEntry::Free));
+    test_fs.save().unwrap();
+    assert_eq!(contents(&mut test_fs, "/x"), vec![2; 600]);
+
+    test_fs.replace("replace_c_test.bin", "/x").unwrap();
+    assert_eq!(test_fs.entries[0].size, 3000);
+    let grown = test_fs.get_blocks(&test_fs.entries[0].clone()).unwrap();
+    assert_eq!(grown.len(), 6);
+    assert!(grown.iter().all(|block| !blocks.contains(block)));
+    assert_eq!(test_fs.pending.len(), 1);
+    test_fs.save().unwrap();
+
+    for name in &["replace_a_test.bin", "replace_b_test.bin", "replace_c_test.bin"] {
+        fs::remove_file(name).expect("Failed removing the file");
+    }
+    let mut test_fs = MicroFS::open_device(test_fs.dev).unwrap();
+    assert!(test_fs.check().unwrap().is_clean());
+    assert_eq!(contents(&mut test_fs, "/x"), vec![3; 3000]);
+    assert_eq!(contents(&mut test_fs, "/y"), vec![1; 2000]);
+}
+
+#[test]
+fn errors() {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    fs::write("replace_dir_test.bin", [1; 100]).unwrap();
+    test_fs.mkdir("/d").unwrap();
+    test_fs.mkdir("/d/replace_dir_test.bin").unwrap();
+    match test_fs.replace("replace_dir_test.bin", "/d") {
+        Err(MicroFsError::IsADirectory(name)) => assert_eq!(name, "/d/replace_dir_test.bin"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.replace("replace_missing_test.bin", "/d") {
+        Err(MicroFsError::NotFound(name)) => assert_eq!(name, "replace_missing_test.bin"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    fs::remove_file("replace_dir_test.bin").expect("Failed removing the file");
+}
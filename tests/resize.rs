@@ -0,0 +1,127 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+
+mod common;
+use common::{FailingDevice, open_clean, snapshot};
+
+fn state(raw_image: Vec<u8>) -> (usize, Vec<(String, Vec<u8>)>) {
+    let mut test_fs = open_clean(raw_image);
+    let mut files = Vec::new();
+    snapshot(&mut test_fs, "/", &mut files);
+    (test_fs.sb.blocks(), files)
+}
+
+// an image of 195 blocks whose files sit at both ends of the data area
+fn image(name: &str) -> Vec<u8> {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 195 * 512)).unwrap();
+    fs::write(name, [1; 40000]).unwrap();
+    test_fs.add_as(name, "/filler").unwrap();
+    fs::write(name, [2; 1500]).unwrap();
+    test_fs.add_as(name, "/a").unwrap();
+    test_fs.mkdir("/d").unwrap();
+    fs::write(name, [3; 20000]).unwrap();
+    test_fs.add_as(name, "/d/b").unwrap();
+    test_fs.save().unwrap();
+    test_fs.del("/filler").unwrap();
+    test_fs.save().unwrap();
+    fs::write(name, [4; 700]).unwrap();
+    test_fs.add_as(name, "/c").unwrap();
+    test_fs.save().unwrap();
+    fs::remove_file(name).expect("Failed removing the file");
+    test_fs.dev.into_inner()
+}
+
+#[test]
+fn grow() {
+    let base = image("resize_grow_test.bin");
+    let (_, files) = state(base.clone());
+    let mut test_fs = MicroFS::open_device(MemDevice::from_vec(base)).unwrap();
+    assert_eq!(test_fs.sb.data_start(), 3);
+    assert!(test_fs.find("/c").unwrap().start == 3);
+    test_fs.resize(400 * 512).unwrap();
+
+    // more blocks than an 8-bit FAT can address, the FAT grows over block 3
+    assert_eq!(test_fs.sb.version, VERSION_FAT16);
+    assert_eq!(test_fs.sb.data_start(), 4);
+    assert_eq!(test_fs.info().total_blocks, 400);
+    assert_eq!(test_fs.dev.as_bytes().len(), 400 * 512);
+    fs::write("resize_grow_new_test.bin", [5; 100000]).unwrap();
+    test_fs.add_as("resize_grow_new_test.bin", "/new").unwrap();
+    test_fs.save().unwrap();
+    fs::remove_file("resize_grow_new_test.bin").expect("Failed removing the file");
+
+    let (blocks, mut after) = state(test_fs.dev.into_inner());
+    assert_eq!(blocks, 400);
+    assert_eq!(after.pop(), Some(("/new".to_string(), vec![5; 100000])));
+    assert_eq!(after, files);
+}
+
+#[test]
+fn shrink() {
+    let base = image("resize_shrink_test.bin");
+    let (_, files) = state(base.clone());
+    let mut test_fs = MicroFS::open_device(MemDevice::from_vec(base.clone())).unwrap();
+    // /d/b ends past block 100
+    test_fs.resize(100 * 512).unwrap();
+    assert_eq!(test_fs.sb.version, VERSION_FAT8);
+    assert_eq!(test_fs.dev.as_bytes().len(), 100 * 512);
+    assert_eq!(state(test_fs.dev.into_inner()), (100, files));
+
+    // the files need 46 blocks, 40 are left between the root directory and the journal
+    let mut test_fs = MicroFS::open_device(MemDevice::from_vec(base.clone())).unwrap();
+    match test_fs.resize(50 * 512) {
+        Err(MicroFsError::NoSpace { .. }) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    assert_eq!(test_fs.sb.blocks(), 195);
+    assert!(test_fs.dev.as_bytes() == &base[..]);
+    assert!(test_fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn wider_names() {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(100), SuperBlock::new("test", 1, 100 * 512)).unwrap();
+    fs::write("resize_names_test.bin", [1; 10]).unwrap();
+    test_fs.add_as("resize_names_test.bin", "/a_name_of_25_bytes_xxxxxx").unwrap();
+    fs::remove_file("resize_names_test.bin").expect("Failed removing the file");
+    test_fs.save().unwrap();
+    // a 32-bit FAT only has room for 24 bytes of name
+    match test_fs.resize(0x10000 * 512) {
+        Err(MicroFsError::NameTooLong(name)) => assert_eq!(name, "/a_name_of_25_bytes_xxxxxx"),
+        res => panic!("unexpected result {:?}", res)
+    }
+    assert_eq!(test_fs.sb.blocks(), 100);
+    assert_eq!(test_fs.dev.as_bytes().len(), 100 * 512);
+}
+
+#[test]
+fn interrupted_resize() {
+    let base = image("resize_interrupted_test.bin");
+    let (_, files) = state(base.clone());
+    for &size in &[100, 400] {
+        let dev = FailingDevice::new(base.clone(), None);
+        let mut test_fs = MicroFS::open_device(dev).unwrap();
+        test_fs.resize(size * 512).unwrap();
+        let total = test_fs.dev.writes;
+
+        let mut resized = 0;
+        for limit in 0..total {
+            let dev = FailingDevice::new(base.clone(), Some(limit));
+            let mut test_fs = MicroFS::open_device(dev).unwrap();
+            match test_fs.resize(size * 512) {
+                Err(MicroFsError::Io(_)) => (),
+                res => panic!("unexpected result {:?} at write {}", res, limit)
+            }
+            let (blocks, after) = state(test_fs.dev.inner.into_inner());
+            assert!(after == files, "files changed after failing at write {}", limit);
+            if blocks == size {
+                resized += 1;
+            } else {
+                assert_eq!(blocks, 195);
+            }
+        }
+        assert!(resized > 0);
+    }
+}
@@ -0,0 +1,65 @@
+extern crate micro_fs;
+use micro_fs::*;
+
+use std::fs;
+
+#[test]
+fn free_and_used() {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(195), SuperBlock::new("test", 1, 100000)).unwrap();
+    assert_eq!(test_fs.data_blocks(), 185);
+    assert_eq!(test_fs.free_blocks(), 185);
+    assert_eq!(test_fs.used_blocks(), 0);
+    assert_eq!(test_fs.slack_bytes(), 0);
+
+    fs::write("space_used_test.bin", [1; 1500]).unwrap();
+    test_fs.add("space_used_test.bin").unwrap();
+    test_fs.mkdir("/d").unwrap();
+    test_fs.add_as("space_used_test.bin", "/d/b").unwrap();
+    fs::remove_file("space_used_test.bin").expect("Failed removing the file");
+
+    assert_eq!(test_fs.used_blocks(), 7);
+    assert_eq!(test_fs.free_blocks(), 178);
+    assert_eq!(test_fs.free_bytes(), 178 * 512);
+    // two files of 1500 bytes in 3 blocks each, the directory table does not count
+    assert_eq!(test_fs.slack_bytes(), 2 * 36);
+    let entries = test_fs.list("/").unwrap();
+    assert_eq!(entries[0].slack(), 36);
+    assert_eq!(test_fs.info().free_blocks, test_fs.free_blocks());
+}
+
+#[test]
+fn check_fit() {
+    let mut sb = SuperBlock::new("test", 1, 20 * 512);
+    sb.journal_blocks = 0;
+    let mut test_fs = MicroFS::format_device(MemDevice::new(20), sb).unwrap();
+    // the super block, the FAT and the root directory leave 17 blocks
+    fs::write("space_fit_a_test.bin", [1; 4000]).unwrap();
+    fs::write("space_fit_b_test.bin", [2; 3000]).unwrap();
+    let files = ["space_fit_a_test.bin", "space_fit_b_test.bin"];
+
+    let report = test_fs.check_fit(&files, "/").unwrap();
+    assert_eq!(report, FitReport { files: 2, needed_entries: 2, needed_blocks: 14, free_blocks: 17, free_entries: Some(16) });
+    assert!(report.fits());
+    let report = test_fs.check_fit(&files[..1], "/").unwrap();
+    assert_eq!(report.needed_blocks, 8);
+
+    test_fs.mkdir("/d").unwrap();
+    test_fs.add_as("space_fit_b_test.bin", "/c").unwrap();
+    test_fs.save().unwrap();
+    let report = test_fs.check_fit(&files, "/d").unwrap();
+    assert_eq!(report, FitReport { files: 2, needed_entries: 2, needed_blocks: 14, free_blocks: 10, free_entries: None });
+    assert!(!report.fits());
+
+    match test_fs.check_fit(&["space_fit_missing_test.bin"], "/") {
+        Err(MicroFsError::NotFound(_)) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+    match test_fs.check_fit(&files, "/c") {
+        Err(MicroFsError::NotADirectory(_)) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+
+    for name in &files {
+        fs::remove_file(name).expect("Failed removing the file");
+    }
+}
@@ -0,0 +1,74 @@
+extern crate micro_fs;
+use micro_fs::*;
+use micro_fs::utils::crc32;
+
+use std::fs;
+use std::io::Write;
+
+fn image(name: &str) -> MicroFS<MemDevice> {
+    let mut sb = SuperBlock::new("test", 1, 200 * 512);
+    sb.version |= VERSION_CHECKSUMS;
+    let mut test_fs = MicroFS::format_device(MemDevice::new(200), sb).unwrap();
+    fs::write(name, [1; 1500]).unwrap();
+    test_fs.add_as(name, "/a").unwrap();
+    test_fs.mkdir("/d").unwrap();
+    fs::write(name, [2; 700]).unwrap();
+    test_fs.add_as(name, "/d/b").unwrap();
+    fs::remove_file(name).expect("Failed removing the file");
+    test_fs.save().unwrap();
+    test_fs
+}
+
+#[test]
+fn checksums() {
+    let test_fs = image("verify_checksums_test.bin");
+    assert_eq!(test_fs.sb.entry_size(), EXTENDED_ENTRY_SIZE);
+    assert_eq!(test_fs.find("/a").unwrap().checksum, crc32(0, &[1; 1500]));
+
+    let mut test_fs = MicroFS::open_device(MemDevice::from_vec(test_fs.dev.into_inner())).unwrap();
+    assert_eq!(test_fs.find("/d/b").unwrap().checksum, crc32(0, &[2; 700]));
+    assert_eq!(test_fs.verify().unwrap(), VerifyReport { files: 2, mismatches: Vec::new() });
+
+    // writes through a handle and replaced files get a new checksum
+    {
+        let mut file = test_fs.open_file("/a", OpenMode::Write).unwrap();
+        file.write_all(&[3; 100]).unwrap();
+    }
+    fs::write("verify_checksums_test.bin", [4; 10]).unwrap();
+    test_fs.replace("verify_checksums_test.bin", "/d/b").unwrap();
+    fs::remove_file("verify_checksums_test.bin").expect("Failed removing the file");
+    // the data of /d/b is not saved yet
+    assert_eq!(test_fs.verify().unwrap().files, 1);
+    test_fs.save().unwrap();
+    assert!(test_fs.verify().unwrap().is_clean());
+    let mut data = vec![3; 100];
+    data.extend_from_slice(&[1; 1400]);
+    assert_eq!(test_fs.find("/a").unwrap().checksum, crc32(0, &data));
+    assert_eq!(test_fs.find("/d/b").unwrap().checksum, crc32(0, &[4; 10]));
+}
+
+#[test]
+fn corrupted() {
+    let test_fs = image("verify_corrupted_test.bin");
+    let start = test_fs.find("/d/b").unwrap().start as usize;
+    let mut raw_image = test_fs.dev.into_inner();
+    raw_image[start * 512 + 10] ^= 0xff;
+
+    let mut test_fs = MicroFS::open_device(MemDevice::from_vec(raw_image)).unwrap();
+    // the chains are fine, only the data changed
+    assert!(test_fs.check().unwrap().is_clean());
+    let report = test_fs.verify().unwrap();
+    assert_eq!(report.files, 2);
+    assert_eq!(report.mismatches.len(), 1);
+    assert_eq!(report.mismatches[0].path, "/d/b");
+    assert_eq!(report.mismatches[0].expected, crc32(0, &[2; 700]));
+}
+
+#[test]
+fn unsupported() {
+    let mut test_fs = MicroFS::format_device(MemDevice::new(100), SuperBlock::new("test", 1, 100 * 512)).unwrap();
+    match test_fs.verify() {
+        Err(MicroFsError::Unsupported(_)) => (),
+        res => panic!("unexpected result {:?}", res)
+    }
+}